@@ -2,13 +2,57 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 
+use seamonkey_core::{cache, install, migrate};
+
+use crate::output;
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
-  #[arg(short, long)]
+  #[arg(short, long, conflicts_with = "game")]
   pub game_dir: Option<PathBuf>,
+  /// A game registered in the config, defaults to the default game there
+  #[arg(long)]
+  pub game: Option<String>,
+  /// The build under bin/ to manage, defaults to the newest one
+  #[arg(long)]
+  pub build: Option<u64>,
+  /// Registered games, defaults to config.toml in the config dir
+  #[arg(long)]
+  pub config: Option<PathBuf>,
   #[arg(short)]
   pub yes_for_all: bool,
+  #[arg(long)]
+  pub dry_run: bool,
+  /// Copy mods left in an older build into the newest one before running
+  #[arg(long)]
+  pub auto_migrate: bool,
+  #[arg(long, value_enum)]
+  pub on_conflict: Option<install::OnConflict>,
+  #[arg(long, value_enum)]
+  pub on_missing_metadata: Option<install::OnMissingMetadata>,
+  #[arg(long, value_enum)]
+  pub on_existing: Option<install::OnExisting>,
+  #[arg(long, value_enum)]
+  pub on_unsigned: Option<install::OnUnsigned>,
+  /// For mods made for other versions of the game, asked when not given
+  #[arg(long, value_enum)]
+  pub on_incompatible: Option<install::OnIncompatible>,
+  /// Trusted publisher keys, defaults to keyring.toml in the config dir
+  #[arg(long)]
+  pub keyring: Option<PathBuf>,
+  /// jsonl prints one event per line, results of list, info and the like as a
+  /// `result` event
+  #[arg(long, value_enum, default_value_t)]
+  pub output: output::OutputFormat,
+  #[arg(long)]
+  pub cache_dir: Option<PathBuf>,
+  /// Size limit of the download cache, in MiB
+  #[arg(long, default_value_t = cache::DEFAULT_MAX_SIZE / 1024 / 1024)]
+  pub cache_max_size: u64,
+  /// Days an unused download is kept in the cache
+  #[arg(long, default_value_t = cache::DEFAULT_MAX_AGE.as_secs() / 24 / 60 / 60)]
+  pub cache_max_age: u64,
   #[command(subcommand)]
   pub subcommand: SubCommand,
 }
@@ -23,6 +67,92 @@ pub enum SubCommand {
   Uninstall {
     #[arg()]
     items: Vec<String>,
+    /// Also uninstall the mods which depend on them, instead of refusing
+    #[arg(long)]
+    cascade: bool,
   },
   Update {},
+  List {
+    #[arg(long)]
+    json: bool,
+  },
+  Info {
+    #[arg()]
+    item: String,
+    #[arg(long)]
+    json: bool,
+  },
+  Verify {
+    #[arg()]
+    items: Vec<String>,
+    #[arg(long)]
+    json: bool,
+  },
+  Repair {
+    #[arg()]
+    item: String,
+  },
+  /// Carry the mods of an older build into the newest one
+  Migrate {
+    /// The build to migrate from, defaults to the newest one with mods installed
+    #[arg(long)]
+    from: Option<u64>,
+    #[arg(long, value_enum, default_value_t)]
+    mode: migrate::Mode,
+    #[arg(long)]
+    json: bool,
+  },
+  Cache {
+    #[command(subcommand)]
+    command: CacheCommand,
+  },
+  /// Manage the games registered in the config
+  Game {
+    #[command(subcommand)]
+    command: GameCommand,
+  },
+  /// List the game installs found in Wine, Proton, Lutris and Bottles prefixes
+  Detect {
+    #[arg(long)]
+    json: bool,
+  },
+}
+
+#[derive(Subcommand)]
+#[command(about, long_about = None)]
+pub enum CacheCommand {
+  List {
+    #[arg(long)]
+    json: bool,
+  },
+  Clean {
+    /// Remove every cached download instead of only expired ones
+    #[arg(long)]
+    all: bool,
+  },
+}
+
+#[derive(Subcommand)]
+#[command(about, long_about = None)]
+pub enum GameCommand {
+  List {
+    #[arg(long)]
+    json: bool,
+  },
+  /// Register a game dir under a name, the first one becomes the default
+  Add {
+    #[arg()]
+    name: String,
+    #[arg()]
+    dir: PathBuf,
+  },
+  Remove {
+    #[arg()]
+    name: String,
+  },
+  /// Make a registered game the default
+  Default {
+    #[arg()]
+    name: String,
+  },
 }
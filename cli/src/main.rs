@@ -1,52 +1,152 @@
-use std::{path::PathBuf, process::exit};
+use std::{path::PathBuf, process::exit, time::Duration};
 
 use clap::Parser;
-use log::{debug, error};
-use temp_dir::TempDir;
-use tokio::fs;
+use log::error;
+use seamonkey_core::{
+  cache::{self, Cache},
+  config::{self, Config},
+  detect,
+  event::{Event, Handler},
+  extract, game, info, install, list, lock,
+  migrate::{self, Mode},
+  record, repair,
+  signature::{self, Keyring},
+  uninstall, update, verify, ModManager,
+};
 
 mod cli;
-mod install;
-mod record;
-mod uninstall;
-mod update;
+mod output;
+mod print;
 
 #[tokio::main]
 async fn main() {
   env_logger::init();
   let cli = cli::Cli::parse();
-  let temp_dir = TempDir::with_prefix("seamonkey").expect("wtf temp dir");
-  run_with_handle_error(cli, &temp_dir).await;
+  run_with_handle_error(cli).await;
 }
 
 #[derive(Debug, thiserror::Error)]
 enum Error {
   #[error("Game dir not provided")]
   GameDirNotProvided,
+  #[error("Several game dirs detected")]
+  AmbiguousGameDir(Vec<PathBuf>),
   #[error("Incorrect game directory structure")]
   IncorrectGameDirectoryStructure,
+  #[error("Build not found: {0}")]
+  BuildNotFound(u64),
   #[error("IO: {0}")]
   Io(std::io::Error),
+  #[error("Lock: {0}")]
+  Lock(lock::Error),
+  #[error("Cache: {0}")]
+  Cache(cache::Error),
+  #[error("Config: {0}")]
+  Config(config::Error),
+  #[error("Keyring: {0}")]
+  Keyring(signature::Error),
   #[error("Install：{0}")]
   Install(install::Error),
   #[error("Uninstall: {0}")]
   Uninstall(uninstall::Error),
   #[error("Update: {0}")]
   Update(update::Error),
+  #[error("List: {0}")]
+  List(list::Error),
+  #[error("Info: {0}")]
+  Info(info::Error),
+  #[error("Verify: {0}")]
+  Verify(verify::Error),
+  #[error("Repair: {0}")]
+  Repair(repair::Error),
+  #[error("Migrate: {0}")]
+  Migrate(migrate::Error),
+  #[error("serde_json: {0}")]
+  SerdeJson(serde_json::Error),
+  #[error("Drift detected: {0} problem(s)")]
+  DriftDetected(usize),
+}
+
+impl From<seamonkey_core::Error> for Error {
+  fn from(err: seamonkey_core::Error) -> Self {
+    match err {
+      seamonkey_core::Error::Lock(err) => Error::Lock(err),
+      seamonkey_core::Error::Install(err) => Error::Install(err),
+      seamonkey_core::Error::Uninstall(err) => Error::Uninstall(err),
+      seamonkey_core::Error::Update(err) => Error::Update(err),
+      seamonkey_core::Error::List(err) => Error::List(err),
+      seamonkey_core::Error::Info(err) => Error::Info(err),
+      seamonkey_core::Error::Verify(err) => Error::Verify(err),
+      seamonkey_core::Error::Repair(err) => Error::Repair(err),
+      seamonkey_core::Error::Migrate(err) => Error::Migrate(err),
+    }
+  }
+}
+
+impl From<game::Error> for Error {
+  fn from(err: game::Error) -> Self {
+    match err {
+      game::Error::Io(err) => Error::Io(err),
+      game::Error::IncorrectGameDirectoryStructure => Error::IncorrectGameDirectoryStructure,
+      game::Error::BuildNotFound(build) => Error::BuildNotFound(build),
+    }
+  }
 }
 
 fn print_error(err: &Error) {
   error!("{:?}", err);
   match err {
     Error::GameDirNotProvided => {
-      eprintln!("未提供游戏目录");
+      eprintln!("未提供游戏目录，配置中也没有默认游戏，且未能自动找到游戏，请使用-g或--game指定");
+    }
+    Error::AmbiguousGameDir(game_dirs) => {
+      eprintln!("找到了多个游戏目录，请使用-g指定其中一个：");
+      for game_dir in game_dirs {
+        eprintln!("  {}", game_dir.display());
+      }
     }
     Error::Io(err) => {
       eprintln!("IO错误：{}", err);
     }
     Error::IncorrectGameDirectoryStructure => {
-      eprintln!("游戏目录结构错误");
+      eprintln!("游戏目录结构错误，bin目录下未找到游戏版本");
+    }
+    Error::BuildNotFound(build) => {
+      eprintln!("bin目录下未找到游戏版本{}", build);
+    }
+    Error::Config(err) => match err {
+      config::Error::Io(err) => {
+        eprintln!("访问配置文件时发生IO错误：{}", err);
+      }
+      config::Error::DeToml(err) => {
+        eprintln!("解析配置文件出错：{}", err);
+      }
+      config::Error::SerToml(err) => {
+        eprintln!("写入配置文件出错：{}", err);
+      }
+      config::Error::UnknownGame(name) => {
+        eprintln!("配置中没有名为{}的游戏", name);
+      }
+    },
+    Error::Cache(err) => match err {
+      cache::Error::Io(err) => {
+        eprintln!("访问下载缓存时发生IO错误：{}", err);
+      }
+      cache::Error::SerdeJson(err) => {
+        eprintln!("序列化下载缓存记录出错：{}", err);
+      }
+    },
+    Error::Keyring(err) => {
+      eprintln!("读取受信任的发布者密钥出错：{}", err);
     }
+    Error::Lock(err) => match err {
+      lock::Error::Io(err) => {
+        eprintln!("锁定Mod目录时发生IO错误：{}", err);
+      }
+      lock::Error::Locked => {
+        eprintln!("另一个seamonkey正在运行，请等待其结束后再试");
+      }
+    },
     Error::Install(err) => match err {
       install::Error::Io(err) => {
         eprintln!("安装时发生IO错误：{}", err);
@@ -75,6 +175,9 @@ fn print_error(err: &Error) {
             eprintln!("  - {}, 元数据未找到", check.installed);
           }
         }
+        if check_list.is_empty() {
+          eprintln!("  该文件不属于任何Mod，如需覆盖请使用--on-conflict overwrite");
+        }
       }
       install::Error::DeToml(err) => {
         eprintln!("解析Mod元数据出错: {}", err);
@@ -86,10 +189,26 @@ fn print_error(err: &Error) {
         record::Error::SerdeJson(err) => {
           eprintln!("解析安装记录出错：{}", err);
         }
+        record::Error::UnsupportedSchemaVersion(schema_version) => {
+          eprintln!(
+            "安装记录由更新版本的seamonkey写入（格式版本{}，当前支持{}），请升级seamonkey",
+            schema_version,
+            record::SCHEMA_VERSION
+          );
+        }
+        record::Error::NotAnObject => {
+          eprintln!("解析安装记录出错：记录不是JSON对象");
+        }
       },
       install::Error::UserInterrupt => {
         eprintln!("安装已被用户取消");
       }
+      install::Error::MetadataNotFound => {
+        eprintln!("未找到元数据，已拒绝安装");
+      }
+      install::Error::AlreadyInstalled(id, version) => {
+        eprintln!("{}已安装，版本{}", id, version);
+      }
       install::Error::Reqwest(err) => {
         eprintln!("网络错误：{}", err);
       }
@@ -105,8 +224,101 @@ fn print_error(err: &Error) {
       install::Error::UrlParse(err) => {
         eprintln!("URL解析错误：{}", err);
       }
-      install::Error::Uninstall(err) => {
-        eprintln!("安装过程中卸载旧Mod错误：{}", err);
+      install::Error::HttpStatus(url, status) => {
+        eprintln!("下载失败，服务器返回状态码{}：{}", status, url);
+      }
+      install::Error::DownloadIncomplete(url, downloaded, total) => {
+        eprintln!("下载未完成（{}/{}字节）：{}", downloaded, total, url);
+      }
+      install::Error::Cache(err) => {
+        eprintln!("访问下载缓存出错：{}", err);
+      }
+      install::Error::InvalidChecksum(checksum) => {
+        eprintln!("指定的SHA256校验值不合法：{}", checksum);
+      }
+      install::Error::ChecksumMismatch(url, expected, actual) => {
+        eprintln!("Mod校验失败，已取消安装：{}", url);
+        eprintln!("  应为：{}", expected);
+        eprintln!("  实际为：{}", actual);
+      }
+      install::Error::Signature(err) => match err {
+        signature::Error::Io(err) => {
+          eprintln!("校验签名时发生IO错误：{}", err);
+        }
+        signature::Error::DeToml(err) => {
+          eprintln!("解析受信任的发布者密钥出错：{}", err);
+        }
+        signature::Error::InvalidPublicKey(publisher, err) => {
+          eprintln!("发布者{}的公钥不合法：{}", publisher, err);
+        }
+        signature::Error::InvalidSignature(err) => {
+          eprintln!("Mod签名不合法：{}", err);
+        }
+        signature::Error::BadSignature(publisher) => {
+          eprintln!(
+            "Mod签名与压缩包不符，压缩包可能已被篡改（发布者{}）",
+            publisher
+          );
+        }
+        signature::Error::UntrustedSignature => {
+          eprintln!("Mod由不受信任的密钥签名，已拒绝安装");
+        }
+      },
+      install::Error::Unsigned(url) => {
+        eprintln!("Mod未签名，已拒绝安装：{}", url);
+      }
+      install::Error::SignerNotAuthor(publisher, authors) => {
+        eprintln!(
+          "签名的发布者{}不是该Mod的作者（{}），已拒绝安装",
+          publisher,
+          authors.join(", ")
+        );
+      }
+      install::Error::UnsignedAuthor(publisher) => {
+        eprintln!(
+          "Mod声称来自受信任的发布者{}，但未经其签名，已拒绝安装",
+          publisher
+        );
+      }
+      install::Error::Extract(err) => {
+        eprint!("已拒绝安装，");
+        print_extract_error(err);
+      }
+      install::Error::Game(err) => {
+        eprintln!("读取游戏版本出错：{}", err);
+      }
+      install::Error::IncompatibleGameVersion(id, game_versions, version) => {
+        eprintln!(
+          "{}要求的游戏版本为{}，与当前游戏版本{}不兼容，已拒绝安装",
+          id, game_versions, version
+        );
+      }
+      install::Error::UnmetDependencies(id, dependencies) => {
+        eprintln!("{}的依赖未满足：", id);
+        for dependency in dependencies {
+          eprintln!("  - {}", dependency);
+        }
+      }
+      install::Error::DependencyWithoutSource(id, dependency) => {
+        eprintln!(
+          "{}依赖于{}，但该依赖未指定更新地址，无法自动安装，请先手动安装",
+          id, dependency
+        );
+      }
+      install::Error::DependencyCycle(id) => {
+        eprintln!("Mod依赖中存在循环：{}", id);
+      }
+      install::Error::DeclaredConflict(id, clashes) => {
+        eprintln!("{}与以下已安装的Mod冲突，已拒绝安装：", id);
+        for (installed, through) in clashes {
+          eprintln!("  - {}（{}）", installed, through);
+        }
+      }
+      install::Error::BreaksDependents(id, broken) => {
+        eprintln!("安装{}会破坏以下Mod的依赖，已拒绝安装：", id);
+        for (dependent, dependency) in broken {
+          eprintln!("  - {}依赖于{}", dependent, dependency);
+        }
       }
     },
     Error::Uninstall(err) => match err {
@@ -119,6 +331,12 @@ fn print_error(err: &Error) {
       uninstall::Error::Record(err) => {
         eprintln!("卸载时访问安装记录发生错误：{}", err);
       }
+      uninstall::Error::RequiredBy(id, dependents) => {
+        eprintln!("{}被以下Mod依赖，已拒绝卸载（使用--cascade一并卸载）：", id);
+        for dependent in dependents {
+          eprintln!("  - {}", dependent);
+        }
+      }
     },
     Error::Update(err) => match err {
       update::Error::Install(err) => {
@@ -128,112 +346,460 @@ fn print_error(err: &Error) {
         eprintln!("更新时读取记录发生错误：{}", err);
       }
     },
+    Error::List(err) => match err {
+      list::Error::Record(err) => {
+        eprintln!("列出Mod时读取记录发生错误：{}", err);
+      }
+      list::Error::Game(err) => {
+        eprintln!("列出Mod时读取游戏版本出错：{}", err);
+      }
+    },
+    Error::Info(err) => match err {
+      info::Error::Record(err) => {
+        eprintln!("查看Mod信息时读取记录发生错误：{}", err);
+      }
+      info::Error::ModNotFound(not_found) => {
+        eprintln!("未找到指定的Mod：{}", not_found);
+      }
+    },
+    Error::Verify(err) => match err {
+      verify::Error::Io(err) => {
+        eprintln!("校验时发生IO错误：{}", err);
+      }
+      verify::Error::Record(err) => {
+        eprintln!("校验时读取记录发生错误：{}", err);
+      }
+      verify::Error::ModNotFound(not_found) => {
+        eprintln!("未找到要校验的Mod：{}", not_found);
+      }
+    },
+    Error::Repair(err) => match err {
+      repair::Error::Io(err) => {
+        eprintln!("修复时发生IO错误：{}", err);
+      }
+      repair::Error::Record(err) => {
+        eprintln!("修复时访问安装记录发生错误：{}", err);
+      }
+      repair::Error::Zip(err) => {
+        eprintln!("修复时访问压缩包出错：{}", err);
+      }
+      repair::Error::Install(err) => {
+        eprintln!("修复时获取压缩包出错：{}", err);
+      }
+      repair::Error::UrlParse(err) => {
+        eprintln!("URL解析错误：{}", err);
+      }
+      repair::Error::UnknownUrlScheme(scheme) => {
+        eprintln!("未知的URL方案：{}", scheme);
+      }
+      repair::Error::ModNotFound(not_found) => {
+        eprintln!("未找到要修复的Mod：{}", not_found);
+      }
+      repair::Error::ArchiveNotFound(from) => {
+        eprintln!("Mod压缩包已不存在：{}", from);
+      }
+      repair::Error::ArchiveChanged(expected, actual) => {
+        eprintln!(
+          "Mod压缩包已变更，无法用于修复：应为{}，实际为{}",
+          expected, actual
+        );
+      }
+      repair::Error::Extract(err) => {
+        eprint!("已拒绝修复，");
+        print_extract_error(err);
+      }
+    },
+    Error::Migrate(err) => match err {
+      migrate::Error::Io(err) => {
+        eprintln!("迁移时发生IO错误：{}", err);
+      }
+      migrate::Error::Record(err) => {
+        eprintln!("迁移时访问安装记录发生错误：{}", err);
+      }
+      migrate::Error::Game(err) => {
+        eprintln!("迁移时读取游戏目录出错：{}", err);
+      }
+      migrate::Error::SameDirectory => {
+        eprintln!("不能迁移到同一个游戏版本");
+      }
+    },
+    Error::SerdeJson(err) => {
+      eprintln!("序列化输出出错：{}", err);
+    }
+    Error::DriftDetected(count) => {
+      eprintln!("校验发现{}个问题", count);
+    }
   }
 }
 
-async fn run_with_handle_error(cli: cli::Cli, temp_dir: &TempDir) {
-  for _ in 0..=3 {
-    if let Err(err) = run(&cli, temp_dir).await {
-      match &err {
-        Error::Install(install::Error::FileConflict(_, check_list)) => {
-          print_error(&err);
-          eprintln!("是否卸载冲突的所有Mod？[y/N]");
-          if cli.yes_for_all || {
-            let mut buf = String::new();
-            match std::io::stdin().read_line(&mut buf).map_err(Error::Io) {
-              Ok(_) if buf.starts_with("Y") || buf.starts_with("y") => true,
-              Ok(_) => false,
-              Err(err) => {
-                print_error(&err);
-                false
-              }
-            }
-          } {
-            if let Err(err) = {
-              let res_mods_dir = find_res_mods_dir(&cli.game_dir).await;
-              match res_mods_dir {
-                Err(err) => Err(err),
-                Ok(res_mods_dir) => uninstall::uninstall(
-                  &res_mods_dir,
-                  check_list
-                    .iter()
-                    .map(|check| check.installed.to_owned())
-                    .collect(),
-                )
-                .await
-                .map_err(Error::Uninstall),
-              }
-            } {
-              print_error(&err);
-            } else {
-              continue;
-            }
-          }
-        }
-        err => print_error(err),
-      }
-      exit(-1);
-    } else {
-      break;
+/// A stable identifier of the error for machine-readable output.
+fn error_code(err: &Error) -> &'static str {
+  match err {
+    Error::GameDirNotProvided => "game_dir_not_provided",
+    Error::AmbiguousGameDir(_) => "ambiguous_game_dir",
+    Error::IncorrectGameDirectoryStructure => "incorrect_game_directory_structure",
+    Error::BuildNotFound(_) => "build_not_found",
+    Error::Io(_) => "io",
+    Error::Config(err) => match err {
+      config::Error::Io(_) => "config.io",
+      config::Error::DeToml(_) => "config.invalid",
+      config::Error::SerToml(_) => "config.serialize",
+      config::Error::UnknownGame(_) => "config.unknown_game",
+    },
+    Error::Cache(err) => match err {
+      cache::Error::Io(_) => "cache.io",
+      cache::Error::SerdeJson(_) => "cache.serialize",
+    },
+    Error::Keyring(_) => "keyring",
+    Error::Lock(err) => match err {
+      lock::Error::Io(_) => "lock.io",
+      lock::Error::Locked => "lock.locked",
+    },
+    Error::Install(err) => match err {
+      install::Error::NoModToInstall => "install.no_mod_to_install",
+      install::Error::UnknownUrlScheme(_) => "install.unknown_url_scheme",
+      install::Error::Record(_) => "install.record",
+      install::Error::Zip(_) => "install.zip",
+      install::Error::Io(_) => "install.io",
+      install::Error::DeToml(_) => "install.invalid_metadata",
+      install::Error::FileConflict(_, _) => "install.file_conflict",
+      install::Error::ModNotFound(_) => "install.mod_not_found",
+      install::Error::UserInterrupt => "install.user_interrupt",
+      install::Error::MetadataNotFound => "install.metadata_not_found",
+      install::Error::AlreadyInstalled(_, _) => "install.already_installed",
+      install::Error::Reqwest(_) => "install.network",
+      install::Error::EmptyResponseHeader(_) => "install.empty_response_header",
+      install::Error::InvalidRequestHeader(_, _) => "install.invalid_response_header",
+      install::Error::ReqwestHeaderToStr(_) => "install.invalid_response_header",
+      install::Error::UrlParse(_) => "install.url_parse",
+      install::Error::HttpStatus(_, _) => "install.http_status",
+      install::Error::DownloadIncomplete(_, _, _) => "install.download_incomplete",
+      install::Error::Cache(_) => "install.cache",
+      install::Error::InvalidChecksum(_) => "install.invalid_checksum",
+      install::Error::ChecksumMismatch(_, _, _) => "install.checksum_mismatch",
+      install::Error::Signature(err) => match err {
+        signature::Error::Io(_) => "install.signature.io",
+        signature::Error::DeToml(_) => "install.signature.invalid_keyring",
+        signature::Error::InvalidPublicKey(_, _) => "install.signature.invalid_public_key",
+        signature::Error::InvalidSignature(_) => "install.signature.invalid",
+        signature::Error::BadSignature(_) => "install.signature.mismatch",
+        signature::Error::UntrustedSignature => "install.signature.untrusted",
+      },
+      install::Error::Unsigned(_) => "install.unsigned",
+      install::Error::SignerNotAuthor(_, _) => "install.signer_not_author",
+      install::Error::UnsignedAuthor(_) => "install.unsigned_author",
+      install::Error::Extract(err) => extract_error_code(err),
+      install::Error::Game(_) => "install.game",
+      install::Error::IncompatibleGameVersion(_, _, _) => "install.incompatible_game_version",
+      install::Error::UnmetDependencies(_, _) => "install.unmet_dependencies",
+      install::Error::DependencyWithoutSource(_, _) => "install.dependency_without_source",
+      install::Error::DependencyCycle(_) => "install.dependency_cycle",
+      install::Error::DeclaredConflict(_, _) => "install.declared_conflict",
+      install::Error::BreaksDependents(_, _) => "install.breaks_dependents",
+    },
+    Error::Uninstall(err) => match err {
+      uninstall::Error::Io(_) => "uninstall.io",
+      uninstall::Error::Record(_) => "uninstall.record",
+      uninstall::Error::ModNotFound(_) => "uninstall.mod_not_found",
+      uninstall::Error::RequiredBy(_, _) => "uninstall.required_by",
+    },
+    Error::Update(err) => match err {
+      update::Error::Record(_) => "update.record",
+      update::Error::Install(_) => "update.install",
+    },
+    Error::List(err) => match err {
+      list::Error::Record(_) => "list.record",
+      list::Error::Game(_) => "list.game",
+    },
+    Error::Info(err) => match err {
+      info::Error::Record(_) => "info.record",
+      info::Error::ModNotFound(_) => "info.mod_not_found",
+    },
+    Error::Verify(err) => match err {
+      verify::Error::Io(_) => "verify.io",
+      verify::Error::Record(_) => "verify.record",
+      verify::Error::ModNotFound(_) => "verify.mod_not_found",
+    },
+    Error::Repair(err) => match err {
+      repair::Error::Io(_) => "repair.io",
+      repair::Error::Record(_) => "repair.record",
+      repair::Error::Zip(_) => "repair.zip",
+      repair::Error::Install(_) => "repair.fetch",
+      repair::Error::UrlParse(_) => "repair.url_parse",
+      repair::Error::UnknownUrlScheme(_) => "repair.unknown_url_scheme",
+      repair::Error::ModNotFound(_) => "repair.mod_not_found",
+      repair::Error::ArchiveNotFound(_) => "repair.archive_not_found",
+      repair::Error::ArchiveChanged(_, _) => "repair.archive_changed",
+      repair::Error::Extract(err) => extract_error_code(err),
+    },
+    Error::Migrate(err) => match err {
+      migrate::Error::Io(_) => "migrate.io",
+      migrate::Error::Record(_) => "migrate.record",
+      migrate::Error::Game(_) => "migrate.game",
+      migrate::Error::SameDirectory => "migrate.same_directory",
+    },
+    Error::SerdeJson(_) => "serialize",
+    Error::DriftDetected(_) => "verify.drift_detected",
+  }
+}
+
+fn print_extract_error(err: &extract::Error) {
+  match err {
+    extract::Error::Zip(err) => {
+      eprintln!("读取压缩包出错：{}", err);
+    }
+    extract::Error::Io(err) => {
+      eprintln!("解压时发生IO错误：{}", err);
+    }
+    extract::Error::AbsolutePath(path) => {
+      eprintln!("压缩包中含有绝对路径：{}", path);
+    }
+    extract::Error::PathTraversal(path) => {
+      eprintln!("压缩包中的路径试图跳出res_mods目录：{}", path);
+    }
+    extract::Error::InvalidPath(path) => {
+      eprintln!("压缩包中含有不合法的路径：{}", path);
+    }
+    extract::Error::SymlinkEntry(path) => {
+      eprintln!("压缩包中含有符号链接：{}", path);
+    }
+    extract::Error::TooManyEntries(count) => {
+      eprintln!(
+        "压缩包中的文件过多（{}个，上限{}个），疑似压缩炸弹",
+        count,
+        extract::MAX_ENTRIES
+      );
+    }
+    extract::Error::TooLarge(size) => {
+      eprintln!(
+        "压缩包解压后过大（至少{}字节，上限{}字节），疑似压缩炸弹",
+        size,
+        extract::MAX_TOTAL_SIZE
+      );
+    }
+    extract::Error::CompressionRatio(path, ratio) => {
+      eprintln!(
+        "压缩包中的文件压缩率异常（{}倍，上限{}倍），疑似压缩炸弹：{}",
+        ratio,
+        extract::MAX_RATIO,
+        path
+      );
+    }
+    extract::Error::SizeMismatch(path) => {
+      eprintln!(
+        "压缩包中的文件解压后大于其声明的大小，疑似压缩炸弹：{}",
+        path
+      );
+    }
+    extract::Error::EscapesTarget(path) => {
+      eprintln!("解压路径经由符号链接跳出了res_mods目录：{}", path.display());
     }
   }
 }
 
-async fn run(cli: &cli::Cli, temp_dir: &TempDir) -> Result<(), Error> {
-  let res_mods_dir = find_res_mods_dir(&cli.game_dir).await?;
+fn extract_error_code(err: &extract::Error) -> &'static str {
+  match err {
+    extract::Error::Zip(_) => "extract.zip",
+    extract::Error::Io(_) => "extract.io",
+    extract::Error::AbsolutePath(_) => "extract.absolute_path",
+    extract::Error::PathTraversal(_) => "extract.path_traversal",
+    extract::Error::InvalidPath(_) => "extract.invalid_path",
+    extract::Error::SymlinkEntry(_) => "extract.symlink",
+    extract::Error::TooManyEntries(_) => "extract.too_many_entries",
+    extract::Error::TooLarge(_) => "extract.too_large",
+    extract::Error::CompressionRatio(_, _) => "extract.compression_ratio",
+    extract::Error::SizeMismatch(_) => "extract.size_mismatch",
+    extract::Error::EscapesTarget(_) => "extract.escapes_target",
+  }
+}
 
-  match &cli.subcommand {
-    cli::SubCommand::Install { items } => install::install(
-      res_mods_dir.as_ref(),
-      items.to_owned(),
-      temp_dir,
-      cli.yes_for_all,
-    )
-    .await
-    .map_err(Error::Install),
-    cli::SubCommand::Uninstall { items } => {
-      uninstall::uninstall(res_mods_dir.as_ref(), items.to_owned())
-        .await
-        .map_err(Error::Uninstall)
-    }
-    cli::SubCommand::Update {} => update::update(res_mods_dir.as_ref(), temp_dir, cli.yes_for_all)
-      .await
-      .map_err(Error::Update),
+async fn run_with_handle_error(cli: cli::Cli) {
+  let handler = output::CliHandler::new(cli.output);
+  if let Err(err) = run(&cli).await {
+    handler.emit(&Event::Error {
+      code: error_code(&err),
+      message: &err.to_string(),
+    });
+    print_error(&err);
+    exit(-1);
   }
 }
 
-async fn find_res_mods_dir(game_dir: &Option<PathBuf>) -> Result<PathBuf, Error> {
-  let game_dir = game_dir.to_owned().ok_or(Error::GameDirNotProvided)?;
-  let bin_dir = game_dir.join("bin");
+async fn run(cli: &cli::Cli) -> Result<(), Error> {
+  let cache = Cache::new(
+    cli.cache_dir.to_owned().unwrap_or_else(Cache::default_dir),
+    cli.cache_max_size * 1024 * 1024,
+    Duration::from_secs(cli.cache_max_age * 24 * 60 * 60),
+  );
+  // The cache does not belong to any game, so it is managed without one.
+  if let cli::SubCommand::Cache { command } = &cli.subcommand {
+    return match command {
+      cli::CacheCommand::List { json } => print::cache_list(
+        &cache.list().await.map_err(Error::Cache)?,
+        print::Format::new(*json, cli.output),
+      )
+      .map_err(Error::SerdeJson),
+      cli::CacheCommand::Clean { all } => print::cache_clean(
+        &cache.clean(*all).await.map_err(Error::Cache)?,
+        print::Format::new(false, cli.output),
+      )
+      .map_err(Error::SerdeJson),
+    };
+  }
 
-  debug!("bin_dir: {:?}", bin_dir);
+  if let cli::SubCommand::Detect { json } = &cli.subcommand {
+    return print::detect(
+      &detect::detect().await,
+      print::Format::new(*json, cli.output),
+    )
+    .map_err(Error::SerdeJson);
+  }
 
-  if !fs::try_exists(bin_dir.as_path()).await.map_err(Error::Io)? {
-    Err(Error::IncorrectGameDirectoryStructure)
-  } else {
-    let dir_names = {
-      let mut read_dir = tokio::fs::read_dir(bin_dir.as_path())
-        .await
-        .map_err(Error::Io)?;
-      let mut dir_names = Vec::new();
-      while let Some(dir) = read_dir.next_entry().await.map_err(Error::Io)? {
-        dir_names.push(dir.file_name().to_owned());
+  let config_path = cli.config.to_owned().unwrap_or_else(Config::default_path);
+  let mut config = Config::load(&config_path).await.map_err(Error::Config)?;
+  if let cli::SubCommand::Game { command } = &cli.subcommand {
+    return match command {
+      cli::GameCommand::List { json } => {
+        print::games(&config, print::Format::new(*json, cli.output)).map_err(Error::SerdeJson)
+      }
+      cli::GameCommand::Add { name, dir } => {
+        game::find_res_mods_dir(dir, None).await?;
+        let dir = tokio::fs::canonicalize(dir).await.map_err(Error::Io)?;
+        config.add_game(name, dir);
+        config.save(&config_path).await.map_err(Error::Config)
+      }
+      cli::GameCommand::Remove { name } => {
+        config.remove_game(name).map_err(Error::Config)?;
+        config.save(&config_path).await.map_err(Error::Config)
+      }
+      cli::GameCommand::Default { name } => {
+        config.set_default(name).map_err(Error::Config)?;
+        config.save(&config_path).await.map_err(Error::Config)
       }
-      dir_names
     };
+  }
 
-    debug!("versions: {:?}", dir_names);
+  let handler = Box::new(output::CliHandler::new(cli.output));
+  let game_dir = match &cli.game_dir {
+    Some(game_dir) => game_dir.to_owned(),
+    None => match config
+      .game_dir(cli.game.as_deref())
+      .map_err(Error::Config)?
+    {
+      Some(game_dir) => game_dir.to_owned(),
+      None => detect_game_dir(handler.as_ref()).await?,
+    },
+  };
+  let build = game::find_build(&game_dir, cli.build).await?;
+  let res_mods_dir = game::res_mods_dir(&game_dir, build);
+  let keyring = Keyring::load(&cli.keyring.to_owned().unwrap_or_else(Keyring::default_path))
+    .await
+    .map_err(Error::Keyring)?;
+  let manager = ModManager::new(res_mods_dir.to_owned(), cache, keyring, handler);
+  let flags = install::Flags {
+    yes_for_all: cli.yes_for_all,
+    dry_run: cli.dry_run,
+    on_conflict: cli.on_conflict,
+    on_missing_metadata: cli.on_missing_metadata,
+    on_existing: cli.on_existing,
+    on_unsigned: cli.on_unsigned,
+    on_incompatible: cli.on_incompatible,
+  };
 
-    let max_version = dir_names
-      .into_iter()
-      .map(|dir_name| dir_name.to_string_lossy().parse::<u64>())
-      .collect::<Result<Vec<_>, _>>()
-      .map_err(|_| Error::IncorrectGameDirectoryStructure)?
-      .into_iter()
-      .max()
-      .map(|x| format!("{}", x))
-      .ok_or(Error::IncorrectGameDirectoryStructure)?;
+  if let cli::SubCommand::Migrate { from, mode, json } = &cli.subcommand {
+    let from = match from {
+      Some(build) => Some(game::res_mods_dir(&game_dir, *build)),
+      None => migrate::pending(&game_dir, build, manager.handler())
+        .await
+        .map_err(Error::Migrate)?,
+    };
+    return match from {
+      Some(from) => {
+        let report = manager.migrate(&from, *mode, flags).await?;
+        print::migrate(&report, cli.dry_run, print::Format::new(*json, cli.output))
+          .map_err(Error::SerdeJson)
+      }
+      None => {
+        manager.handler().message("没有需要迁移的Mod");
+        Ok(())
+      }
+    };
+  }
+  // Only commands which change the mods look for ones left in an older build,
+  // reading or planning never migrates anything.
+  let mutating = matches!(
+    cli.subcommand,
+    cli::SubCommand::Install { .. }
+      | cli::SubCommand::Uninstall { .. }
+      | cli::SubCommand::Update {}
+      | cli::SubCommand::Repair { .. }
+  );
+  if mutating && !cli.dry_run {
+    match migrate::pending(&game_dir, build, manager.handler()).await {
+      Ok(Some(from)) if cli.auto_migrate => {
+        let report = manager.migrate(&from, Mode::Copy, flags).await?;
+        print::migrate(&report, cli.dry_run, print::Format::new(false, cli.output))
+          .map_err(Error::SerdeJson)?;
+      }
+      Ok(Some(from)) => manager.handler().message(&format!(
+        "游戏已更新，已安装的Mod仍在{}中，可运行migrate将其迁移到{}",
+        from.display(),
+        res_mods_dir.display()
+      )),
+      Ok(None) => {}
+      Err(err) => error!("failed to check for a pending migration: {}", err),
+    }
+  }
 
-    Ok(bin_dir.join(max_version).join("res_mods"))
+  match &cli.subcommand {
+    cli::SubCommand::Install { items } => Ok(manager.install(items.to_owned(), flags).await?),
+    cli::SubCommand::Uninstall { items, cascade } => Ok(
+      manager
+        .uninstall(items.to_owned(), *cascade, cli.dry_run)
+        .await?,
+    ),
+    cli::SubCommand::Update {} => Ok(manager.update(flags).await?),
+    cli::SubCommand::List { json } => print::list(
+      &manager.list().await?,
+      print::Format::new(*json, cli.output),
+    )
+    .map_err(Error::SerdeJson),
+    cli::SubCommand::Info { item, json } => print::info(
+      &manager.info(item).await?,
+      print::Format::new(*json, cli.output),
+    )
+    .map_err(Error::SerdeJson),
+    cli::SubCommand::Verify { items, json } => {
+      let problems = manager.verify(items).await?;
+      print::verify(&problems, print::Format::new(*json, cli.output)).map_err(Error::SerdeJson)?;
+      if problems.is_empty() {
+        Ok(())
+      } else {
+        Err(Error::DriftDetected(problems.len()))
+      }
+    }
+    cli::SubCommand::Repair { item } => Ok(manager.repair(item, cli.dry_run).await?),
+    cli::SubCommand::Cache { .. }
+    | cli::SubCommand::Detect { .. }
+    | cli::SubCommand::Game { .. }
+    | cli::SubCommand::Migrate { .. } => {
+      unreachable!("handled before")
+    }
+  }
+}
+
+/// The game dir to use when none is given, if exactly one install is found.
+async fn detect_game_dir(handler: &dyn Handler) -> Result<PathBuf, Error> {
+  let mut candidates = detect::detect().await;
+  match candidates.len() {
+    0 => Err(Error::GameDirNotProvided),
+    1 => {
+      let game_dir = candidates.remove(0).game_dir;
+      handler.message(&format!("使用自动找到的游戏目录：{}", game_dir.display()));
+      Ok(game_dir)
+    }
+    _ => Err(Error::AmbiguousGameDir(
+      candidates.into_iter().map(|x| x.game_dir).collect(),
+    )),
   }
 }
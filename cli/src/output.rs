@@ -0,0 +1,193 @@
+use std::{
+  collections::HashMap,
+  io::IsTerminal,
+  sync::{Mutex, MutexGuard},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::error;
+use seamonkey_core::event::{Event, Handler, Prompt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+  #[default]
+  Text,
+  Jsonl,
+}
+
+/// Progress bars drawn on stderr: one per running download, plus an overall
+/// bar when several items are installed at once.
+struct Progress {
+  multi: MultiProgress,
+  overall: Option<ProgressBar>,
+  downloads: HashMap<String, ProgressBar>,
+}
+
+impl Progress {
+  fn new() -> Self {
+    Self {
+      multi: MultiProgress::new(),
+      overall: None,
+      downloads: HashMap::new(),
+    }
+  }
+
+  fn event(&mut self, event: &Event) {
+    match event {
+      Event::ItemStarted { item, index, count } if *count > 1 => {
+        let overall = self.overall.get_or_insert_with(|| {
+          let bar = self.multi.add(ProgressBar::new(*count as u64));
+          bar.set_style(
+            ProgressStyle::with_template("总进度 [{bar:40}] {pos}/{len} {wide_msg}")
+              .expect("wtf progress template")
+              .progress_chars("=> "),
+          );
+          bar
+        });
+        overall.set_position(*index as u64);
+        overall.set_message(item.to_string());
+      }
+      Event::ItemFinished { index, count, .. } => {
+        if let Some(overall) = &self.overall {
+          overall.set_position(*index as u64 + 1);
+          if index + 1 == *count {
+            overall.finish_and_clear();
+            self.overall = None;
+          }
+        }
+      }
+      Event::DownloadStarted { url, total } => {
+        let bar = match total {
+          Some(total) => {
+            let bar = ProgressBar::new(*total);
+            bar.set_style(
+              ProgressStyle::with_template(
+                "{msg} [{bar:40}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+              )
+              .expect("wtf progress template")
+              .progress_chars("=> "),
+            );
+            bar
+          }
+          None => {
+            let bar = ProgressBar::new_spinner();
+            bar.set_style(
+              ProgressStyle::with_template("{spinner} {msg} {bytes} {bytes_per_sec}")
+                .expect("wtf progress template"),
+            );
+            bar
+          }
+        };
+        let bar = match &self.overall {
+          Some(overall) => self.multi.insert_before(overall, bar),
+          None => self.multi.add(bar),
+        };
+        bar.set_message(download_name(url).to_owned());
+        self.downloads.insert(url.to_string(), bar);
+      }
+      Event::DownloadProgress {
+        url, downloaded, ..
+      } => {
+        if let Some(bar) = self.downloads.get(*url) {
+          bar.set_position(*downloaded);
+        }
+      }
+      Event::DownloadFinished { url, .. } => {
+        if let Some(bar) = self.downloads.remove(*url) {
+          bar.finish_and_clear();
+          self.multi.remove(&bar);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+/// The last path segment of a download, which is usually the archive name.
+fn download_name(url: &str) -> &str {
+  url
+    .rsplit('/')
+    .find(|segment| !segment.is_empty())
+    .unwrap_or(url)
+}
+
+/// Prints messages for humans, or every event as one JSON object per line in
+/// `jsonl` mode. Answers to prompts are read from stdin either way.
+pub struct CliHandler {
+  output_format: OutputFormat,
+  /// Only present for text output on a terminal.
+  progress: Option<Mutex<Progress>>,
+}
+
+impl CliHandler {
+  pub fn new(output_format: OutputFormat) -> Self {
+    let progress = (output_format == OutputFormat::Text && std::io::stderr().is_terminal())
+      .then(|| Mutex::new(Progress::new()));
+    Self {
+      output_format,
+      progress,
+    }
+  }
+
+  pub fn emit(&self, event: &Event) {
+    if self.output_format == OutputFormat::Jsonl {
+      match serde_json::to_string(event) {
+        Ok(line) => println!("{}", line),
+        Err(err) => error!("failed to serialize event {:?}: {}", event, err),
+      }
+    }
+  }
+
+  fn progress(&self) -> Option<MutexGuard<'_, Progress>> {
+    self
+      .progress
+      .as_ref()
+      .map(|progress| progress.lock().expect("wtf progress lock"))
+  }
+
+  /// Runs `f` with the progress bars hidden, so that its output is not torn.
+  fn suspend<T>(&self, f: impl FnOnce() -> T) -> T {
+    match self.progress() {
+      Some(progress) => progress.multi.suspend(f),
+      None => f(),
+    }
+  }
+}
+
+impl Handler for CliHandler {
+  fn event(&self, event: &Event) {
+    match (self.output_format, event) {
+      (OutputFormat::Text, Event::Message { text }) => self.suspend(|| println!("{}", text)),
+      (OutputFormat::Text, event) => match self.progress() {
+        Some(mut progress) => progress.event(event),
+        None => match event {
+          Event::DownloadStarted { url, .. } => eprintln!("正在下载：{}", url),
+          Event::DownloadFinished { url, downloaded } => {
+            eprintln!("下载完成：{}（{}字节）", url, downloaded)
+          }
+          _ => {}
+        },
+      },
+      (OutputFormat::Jsonl, event) => self.emit(event),
+    }
+  }
+
+  fn confirm(&self, kind: Prompt, text: &str, default: bool) -> bool {
+    self.suspend(|| {
+      match self.output_format {
+        OutputFormat::Text => eprintln!("{}", text),
+        OutputFormat::Jsonl => self.emit(&Event::Prompt { kind, text }),
+      }
+      let mut buf = String::new();
+      if let Err(err) = std::io::stdin().read_line(&mut buf) {
+        error!("failed to read answer: {}", err);
+        return false;
+      }
+      match buf.trim_start().chars().next() {
+        Some('y' | 'Y') => true,
+        Some('n' | 'N') => false,
+        _ => default,
+      }
+    })
+  }
+}
@@ -0,0 +1,315 @@
+use seamonkey_core::{
+  cache::{CacheEntry, CleanReport},
+  config::Config,
+  detect::{Candidate, FoundBy, Runner},
+  event::Event,
+  info::InfoItem,
+  list::ListItem,
+  migrate::{MigrateReport, NotApplied},
+  verify::{Problem, ProblemKind},
+};
+
+use serde::Serialize;
+
+use crate::output::OutputFormat;
+
+/// How a command prints what it found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Text,
+  /// A single JSON document, as asked for with `--json`.
+  Json,
+  /// A `result` event among the others of `--output jsonl`.
+  Jsonl,
+}
+
+impl Format {
+  pub fn new(json: bool, output: OutputFormat) -> Self {
+    match output {
+      OutputFormat::Jsonl => Self::Jsonl,
+      OutputFormat::Text if json => Self::Json,
+      OutputFormat::Text => Self::Text,
+    }
+  }
+}
+
+fn print_json(
+  format: Format,
+  command: &str,
+  data: &(impl Serialize + ?Sized),
+) -> Result<(), serde_json::Error> {
+  let line = match format {
+    Format::Jsonl => serde_json::to_string(&Event::Result {
+      command,
+      data: serde_json::to_value(data)?,
+    })?,
+    Format::Json | Format::Text => serde_json::to_string(data)?,
+  };
+  println!("{}", line);
+  Ok(())
+}
+
+pub fn list(items: &[ListItem], format: Format) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "list", items);
+  }
+
+  if items.is_empty() {
+    println!("未安装任何Mod");
+  } else {
+    for item in items {
+      println!("{}", item.install_id);
+      if let (Some(name), Some(id), Some(version)) = (&item.name, &item.id, &item.version) {
+        println!("  名称：{}({})", name, id);
+        println!("  版本：{}", version);
+      } else {
+        println!("  元数据未找到");
+      }
+      println!("  来源：{}", item.from);
+      println!("  更新时间：{}", item.last_update_time);
+      println!("  文件数：{}", item.file_count);
+      if let (Some(false), Some(game_versions)) = (item.compatible, &item.game_versions) {
+        println!("  不兼容当前游戏版本，要求的游戏版本为{}", game_versions);
+      }
+    }
+  }
+
+  Ok(())
+}
+
+pub fn info(info: &InfoItem, format: Format) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "info", info);
+  }
+
+  let record_item = &info.record_item;
+  println!("安装ID：{}", info.install_id);
+  if let Some(metadata) = &record_item.metadata {
+    println!("名称：{}({})", metadata.name, metadata.id);
+    println!("版本：{}", metadata.version);
+    println!("描述：{}", metadata.description);
+    println!("作者：{}", metadata.authors.join(", "));
+    println!("主页：{}", metadata.url);
+    println!("更新地址：{}", metadata.update);
+    if let Some(game_versions) = &metadata.game_versions {
+      println!("游戏版本：{}", game_versions);
+    }
+    if !metadata.depends.is_empty() {
+      println!("依赖：");
+      for dependency in metadata.depends.iter() {
+        println!("  - {}", dependency);
+      }
+    }
+    if !metadata.conflicts.is_empty() {
+      println!("冲突：{}", metadata.conflicts.join(", "));
+    }
+    if !metadata.provides.is_empty() {
+      println!("提供：{}", metadata.provides.join(", "));
+    }
+  } else {
+    println!("元数据未找到");
+  }
+  println!("来源：{}", record_item.from);
+  println!("SHA256：{}", record_item.sha256);
+  if let Some(signed_by) = &record_item.signed_by {
+    println!("签名者：{}", signed_by);
+  }
+  println!("更新时间：{}", record_item.last_update_time);
+  println!("文件（{}）：", record_item.files.len());
+  for file in record_item.files.iter() {
+    println!("  - {}", file.to_string_lossy());
+  }
+
+  Ok(())
+}
+
+pub fn verify(problems: &[Problem], format: Format) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "verify", problems);
+  }
+
+  if problems.is_empty() {
+    println!("所有文件完好");
+  } else {
+    for problem in problems.iter() {
+      let kind = match problem.kind {
+        ProblemKind::Missing => "缺失",
+        ProblemKind::Modified => "已修改",
+        ProblemKind::Unowned => "不属于任何Mod",
+      };
+      if let Some(install_id) = &problem.install_id {
+        println!(
+          "{}：{}（{}）",
+          kind,
+          problem.path.to_string_lossy(),
+          install_id
+        );
+      } else {
+        println!("{}：{}", kind, problem.path.to_string_lossy());
+      }
+    }
+  }
+
+  Ok(())
+}
+
+pub fn cache_list(entries: &[CacheEntry], format: Format) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "cache_list", entries);
+  }
+
+  if entries.is_empty() {
+    println!("下载缓存为空");
+  } else {
+    for entry in entries {
+      println!("{}", entry.url);
+      println!("  SHA256：{}", entry.sha256);
+      println!("  大小：{}字节", entry.size);
+      println!("  下载时间：{}", format_time(entry.downloaded_at));
+      println!("  上次使用：{}", format_time(entry.last_used_at));
+    }
+  }
+
+  Ok(())
+}
+
+pub fn cache_clean(report: &CleanReport, format: Format) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "cache_clean", report);
+  }
+
+  if report.removed.is_empty() && report.removed_parts == 0 {
+    println!("没有需要清理的缓存");
+  } else {
+    for entry in report.removed.iter() {
+      println!("已清理：{}", entry.url);
+    }
+    if report.removed_parts > 0 {
+      println!("已清理{}个未完成的下载", report.removed_parts);
+    }
+    println!("共释放{}字节", report.freed);
+  }
+
+  Ok(())
+}
+
+pub fn detect(candidates: &[Candidate], format: Format) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "detect", candidates);
+  }
+
+  if candidates.is_empty() {
+    println!("未找到游戏");
+  } else {
+    for candidate in candidates {
+      let runner = match candidate.runner {
+        Runner::Wine => "Wine",
+        Runner::Proton => "Proton",
+        Runner::Lutris => "Lutris",
+        Runner::Bottles => "Bottles",
+      };
+      let found_by = match candidate.found_by {
+        FoundBy::Layout => "目录结构",
+        FoundBy::GameCenter => "Lesta游戏中心记录",
+      };
+      println!("{}", candidate.game_dir.to_string_lossy());
+      println!(
+        "  前缀：{}（{}）",
+        candidate.prefix.to_string_lossy(),
+        runner
+      );
+      println!("  依据：{}", found_by);
+    }
+  }
+
+  Ok(())
+}
+
+pub fn games(config: &Config, format: Format) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "game_list", config);
+  }
+
+  if config.games.is_empty() {
+    println!("配置中没有游戏");
+  } else {
+    for (name, game) in config.games.iter() {
+      if config.default.as_deref() == Some(name.as_str()) {
+        println!("{}（默认）", name);
+      } else {
+        println!("{}", name);
+      }
+      println!("  目录：{}", game.dir.to_string_lossy());
+    }
+  }
+
+  Ok(())
+}
+
+pub fn migrate(
+  report: &MigrateReport,
+  dry_run: bool,
+  format: Format,
+) -> Result<(), serde_json::Error> {
+  if format != Format::Text {
+    return print_json(format, "migrate", report);
+  }
+
+  println!(
+    "从{}迁移到{}：",
+    report.from.to_string_lossy(),
+    report.to.to_string_lossy()
+  );
+  let mut not_applied_count = 0;
+  let mut reinstall_may_help = false;
+  for item in report.items.iter() {
+    let label = match (&item.name, &item.id) {
+      (Some(name), Some(id)) => format!("{}({})", name, id),
+      _ => item.install_id.to_owned(),
+    };
+    let Some(not_applied) = &item.not_applied else {
+      if dry_run {
+        println!("[演练] 将迁移：{}", label);
+      } else {
+        println!("已迁移：{}", label);
+      }
+      continue;
+    };
+    not_applied_count += 1;
+    println!("无法迁移：{}", label);
+    match not_applied {
+      NotApplied::FilesMissing(files) => {
+        reinstall_may_help = true;
+        println!("  以下文件在旧版本中缺失或已被修改：");
+        for file in files {
+          println!("    - {}", file.to_string_lossy());
+        }
+      }
+      NotApplied::FilesShadowed(files) => {
+        println!("  以下文件已由新版本的游戏提供：");
+        for file in files {
+          println!("    - {}", file.to_string_lossy());
+        }
+      }
+      NotApplied::ReinstallFailed(err) => {
+        println!("  重新安装失败：{}", err);
+      }
+    }
+  }
+  if reinstall_may_help {
+    println!(
+      "{}个Mod未能迁移，可尝试使用--mode reinstall重新安装",
+      not_applied_count
+    );
+  } else if not_applied_count > 0 {
+    println!("{}个Mod未能迁移", not_applied_count);
+  }
+
+  Ok(())
+}
+
+fn format_time(secs: u64) -> String {
+  chrono::DateTime::from_timestamp(secs as i64, 0)
+    .map(|time| time.with_timezone(&chrono::Local).to_string())
+    .unwrap_or_else(|| secs.to_string())
+}
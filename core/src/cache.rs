@@ -0,0 +1,427 @@
+use std::{
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use url::Url;
+
+use crate::dirs;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("serde_json: {0}")]
+  SerdeJson(serde_json::Error),
+}
+
+/// Total size of the cached downloads kept by default.
+pub const DEFAULT_MAX_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+/// How long an unused download is kept by default.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const ENTRY_EXTENSION: &str = "json";
+const PART_EXTENSION: &str = "part";
+const VALIDATOR_EXTENSION: &str = "validator";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+  pub url: String,
+  pub sha256: String,
+  pub size: u64,
+  /// Seconds since the unix epoch.
+  pub downloaded_at: u64,
+  pub last_used_at: u64,
+  /// What the server identified the download with, to revalidate it before reuse.
+  #[serde(default, flatten)]
+  pub validator: Validator,
+}
+
+/// The `ETag` and `Last-Modified` headers a download was served with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validator {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub etag: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub last_modified: Option<String>,
+}
+
+impl Validator {
+  pub fn is_empty(&self) -> bool {
+    self.etag.is_none() && self.last_modified.is_none()
+  }
+
+  /// The value to send as `If-Range`: a strong `ETag`, or else `Last-Modified`.
+  pub fn if_range(&self) -> Option<&str> {
+    self
+      .etag
+      .as_deref()
+      .filter(|etag| !etag.starts_with("W/"))
+      .or(self.last_modified.as_deref())
+  }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CleanReport {
+  pub removed: Vec<CacheEntry>,
+  /// Part files of unfinished downloads which were removed.
+  pub removed_parts: usize,
+  pub freed: u64,
+}
+
+/// Downloads kept between runs, keyed by the hash of their URL and checked
+/// against the hash of their content before reuse.
+#[derive(Debug, Clone)]
+pub struct Cache {
+  dir: PathBuf,
+  max_size: u64,
+  max_age: Duration,
+}
+
+impl Cache {
+  pub fn new(dir: PathBuf, max_size: u64, max_age: Duration) -> Self {
+    Self {
+      dir,
+      max_size,
+      max_age,
+    }
+  }
+
+  pub fn default_dir() -> PathBuf {
+    dirs::cache_dir().join("downloads")
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// The fragment is left out, as it is never sent to the server.
+  fn key(url: &Url) -> String {
+    let mut url = url.to_owned();
+    url.set_fragment(None);
+    sha256::digest(url.to_string())
+  }
+
+  fn data_path(&self, key: &str) -> PathBuf {
+    self.dir.join(key)
+  }
+
+  fn entry_path(&self, key: &str) -> PathBuf {
+    self.dir.join(key).with_extension(ENTRY_EXTENSION)
+  }
+
+  /// Where a download of `url` is written until it is complete.
+  pub async fn part_path(&self, url: &Url) -> Result<PathBuf, Error> {
+    fs::create_dir_all(&self.dir).await.map_err(Error::Io)?;
+    Ok(self.dir.join(Self::key(url)).with_extension(PART_EXTENSION))
+  }
+
+  fn validator_path(&self, key: &str) -> PathBuf {
+    self.dir.join(key).with_extension(VALIDATOR_EXTENSION)
+  }
+
+  /// The validator the part file of `url` was downloaded with, to resume it
+  /// only while the file on the server is unchanged.
+  pub async fn part_validator(&self, url: &Url) -> Result<Option<Validator>, Error> {
+    let key = Self::key(url);
+    match fs::read(self.validator_path(&key)).await {
+      Ok(content) => match serde_json::from_slice(&content) {
+        Ok(validator) => Ok(Some(validator)),
+        Err(err) => {
+          warn!("part validator {} is unreadable, discarding: {}", key, err);
+          remove_if_exists(&self.validator_path(&key)).await?;
+          Ok(None)
+        }
+      },
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(Error::Io(err)),
+    }
+  }
+
+  /// Records the validator of a part file which is being started over, or
+  /// forgets it when the server sent none.
+  pub async fn set_part_validator(&self, url: &Url, validator: &Validator) -> Result<(), Error> {
+    let validator_path = self.validator_path(&Self::key(url));
+    if validator.is_empty() {
+      remove_if_exists(&validator_path).await
+    } else {
+      fs::write(
+        validator_path,
+        serde_json::to_vec(validator).map_err(Error::SerdeJson)?,
+      )
+      .await
+      .map_err(Error::Io)
+    }
+  }
+
+  /// The validator the cached download of `url` was served with.
+  pub async fn validator(&self, url: &Url) -> Result<Validator, Error> {
+    Ok(
+      self
+        .read_entry(&Self::key(url))
+        .await?
+        .map(|entry| entry.validator)
+        .unwrap_or_default(),
+    )
+  }
+
+  /// Returns the cached download of `url`, if there is one whose content
+  /// still matches the recorded hash, and `expected_sha256` when given.
+  pub async fn lookup(
+    &self,
+    url: &Url,
+    expected_sha256: Option<&str>,
+  ) -> Result<Option<PathBuf>, Error> {
+    let key = Self::key(url);
+    let Some(mut entry) = self.read_entry(&key).await? else {
+      return Ok(None);
+    };
+    if expected_sha256.is_some_and(|expected| expected != entry.sha256) {
+      debug!("cached download of {} has changed, discarding", url);
+      self.remove(&key).await?;
+      return Ok(None);
+    }
+    let data_path = self.data_path(&key);
+    let sha256 = match sha256::try_async_digest(&data_path).await {
+      Ok(sha256) => sha256,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+        self.remove(&key).await?;
+        return Ok(None);
+      }
+      Err(err) => return Err(Error::Io(err)),
+    };
+    if sha256 != entry.sha256 {
+      warn!("cached download of {} is corrupted, discarding", url);
+      self.remove(&key).await?;
+      return Ok(None);
+    }
+
+    debug!("cache hit: {}", url);
+    entry.last_used_at = now();
+    self.write_entry(&key, &entry).await?;
+    Ok(Some(data_path))
+  }
+
+  /// Moves a completed download into the cache and returns its new path.
+  pub async fn store(&self, url: &Url, part_path: &Path) -> Result<PathBuf, Error> {
+    let key = Self::key(url);
+    let data_path = self.data_path(&key);
+    let sha256 = sha256::try_async_digest(part_path)
+      .await
+      .map_err(Error::Io)?;
+    let size = fs::metadata(part_path).await.map_err(Error::Io)?.len();
+    let validator = self.part_validator(url).await?.unwrap_or_default();
+    fs::rename(part_path, &data_path).await.map_err(Error::Io)?;
+    remove_if_exists(&self.validator_path(&key)).await?;
+    let now = now();
+    let mut url = url.to_owned();
+    url.set_fragment(None);
+    self
+      .write_entry(
+        &key,
+        &CacheEntry {
+          url: url.to_string(),
+          sha256,
+          size,
+          downloaded_at: now,
+          last_used_at: now,
+          validator,
+        },
+      )
+      .await?;
+
+    // Make room for the new download, but never evict the download itself.
+    self.prune(Some(&key)).await?;
+    Ok(data_path)
+  }
+
+  /// Forgets the cached download of `url`.
+  pub async fn evict(&self, url: &Url) -> Result<(), Error> {
+    self.remove(&Self::key(url)).await
+  }
+
+  /// Lists the cached downloads, most recently used first.
+  pub async fn list(&self) -> Result<Vec<CacheEntry>, Error> {
+    let mut entries = self
+      .read_entries()
+      .await?
+      .into_iter()
+      .map(|(_, entry)| entry)
+      .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_used_at));
+    Ok(entries)
+  }
+
+  /// Removes expired downloads and the least recently used ones beyond the
+  /// size limit, or every download when `all` is set.
+  pub async fn clean(&self, all: bool) -> Result<CleanReport, Error> {
+    if all {
+      let mut report = CleanReport::default();
+      for (key, entry) in self.read_entries().await? {
+        self.remove(&key).await?;
+        report.freed += entry.size;
+        report.removed.push(entry);
+      }
+      for (key, _, size) in self.read_parts().await? {
+        self.remove_part(&key).await?;
+        report.removed_parts += 1;
+        report.freed += size;
+      }
+      Ok(report)
+    } else {
+      self.prune(None).await
+    }
+  }
+
+  /// Evicts downloads, and the part files of unfinished ones, which are
+  /// expired or beyond the size limit, least recently used first.
+  async fn prune(&self, keep: Option<&str>) -> Result<CleanReport, Error> {
+    let mut candidates = self
+      .read_entries()
+      .await?
+      .into_iter()
+      .map(|(key, entry)| (key, entry.last_used_at, entry.size, Some(entry)))
+      .chain(
+        self
+          .read_parts()
+          .await?
+          .into_iter()
+          .map(|(key, modified, size)| (key, modified, size, None)),
+      )
+      .collect::<Vec<_>>();
+    // Oldest first, so that they are evicted first.
+    candidates.sort_by_key(|(_, last_used_at, _, _)| *last_used_at);
+
+    let expired_before = now().saturating_sub(self.max_age.as_secs());
+    let mut size = candidates.iter().map(|(_, _, size, _)| size).sum::<u64>();
+    let mut report = CleanReport::default();
+    for (key, last_used_at, size_, entry) in candidates {
+      if Some(key.as_str()) == keep {
+        continue;
+      }
+      if last_used_at < expired_before || size > self.max_size {
+        match entry {
+          Some(entry) => {
+            debug!("evicting cached download: {}", entry.url);
+            self.remove(&key).await?;
+            report.removed.push(entry);
+          }
+          None => {
+            debug!("evicting unfinished download: {}", key);
+            self.remove_part(&key).await?;
+            report.removed_parts += 1;
+          }
+        }
+        size -= size_;
+        report.freed += size_;
+      }
+    }
+    Ok(report)
+  }
+
+  async fn read_entry(&self, key: &str) -> Result<Option<CacheEntry>, Error> {
+    match fs::read(self.entry_path(key)).await {
+      Ok(content) => match serde_json::from_slice(&content) {
+        Ok(entry) => Ok(Some(entry)),
+        Err(err) => {
+          warn!("cache entry {} is unreadable, discarding: {}", key, err);
+          self.remove(key).await?;
+          Ok(None)
+        }
+      },
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(Error::Io(err)),
+    }
+  }
+
+  async fn write_entry(&self, key: &str, entry: &CacheEntry) -> Result<(), Error> {
+    fs::write(
+      self.entry_path(key),
+      serde_json::to_vec(entry).map_err(Error::SerdeJson)?,
+    )
+    .await
+    .map_err(Error::Io)
+  }
+
+  async fn read_entries(&self) -> Result<Vec<(String, CacheEntry)>, Error> {
+    let mut read_dir = match fs::read_dir(&self.dir).await {
+      Ok(read_dir) => read_dir,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(err) => return Err(Error::Io(err)),
+    };
+    let mut entries = Vec::new();
+    while let Some(dir_entry) = read_dir.next_entry().await.map_err(Error::Io)? {
+      let path = dir_entry.path();
+      if path.extension().is_some_and(|ext| ext == ENTRY_EXTENSION) {
+        if let Some(key) = path.file_stem().map(|x| x.to_string_lossy().to_string()) {
+          if let Some(entry) = self.read_entry(&key).await? {
+            entries.push((key, entry));
+          }
+        }
+      }
+    }
+    Ok(entries)
+  }
+
+  /// The part files of unfinished downloads, with their key, the time they
+  /// were last written to in seconds since the unix epoch, and their size.
+  async fn read_parts(&self) -> Result<Vec<(String, u64, u64)>, Error> {
+    let mut read_dir = match fs::read_dir(&self.dir).await {
+      Ok(read_dir) => read_dir,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(err) => return Err(Error::Io(err)),
+    };
+    let mut parts = Vec::new();
+    while let Some(dir_entry) = read_dir.next_entry().await.map_err(Error::Io)? {
+      let path = dir_entry.path();
+      if path.extension().is_some_and(|ext| ext == PART_EXTENSION) {
+        if let Some(key) = path.file_stem().map(|x| x.to_string_lossy().to_string()) {
+          let metadata = dir_entry.metadata().await.map_err(Error::Io)?;
+          let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+            .map(|x| x.as_secs())
+            .unwrap_or_default();
+          parts.push((key, modified, metadata.len()));
+        }
+      }
+    }
+    Ok(parts)
+  }
+
+  async fn remove(&self, key: &str) -> Result<(), Error> {
+    for path in [self.data_path(key), self.entry_path(key)] {
+      remove_if_exists(&path).await?;
+    }
+    Ok(())
+  }
+
+  async fn remove_part(&self, key: &str) -> Result<(), Error> {
+    for path in [
+      self.dir.join(key).with_extension(PART_EXTENSION),
+      self.validator_path(key),
+    ] {
+      remove_if_exists(&path).await?;
+    }
+    Ok(())
+  }
+}
+
+async fn remove_if_exists(path: &Path) -> Result<(), Error> {
+  match fs::remove_file(path).await {
+    Ok(()) => Ok(()),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+    Err(err) => Err(Error::Io(err)),
+  }
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|x| x.as_secs())
+    .unwrap_or_default()
+}
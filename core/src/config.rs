@@ -0,0 +1,110 @@
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::dirs;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("TOML: {0}")]
+  DeToml(toml::de::Error),
+  #[error("TOML: {0}")]
+  SerToml(toml::ser::Error),
+  #[error("Unknown game: {0}")]
+  UnknownGame(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Game {
+  pub dir: PathBuf,
+}
+
+/// Named game installs, read from a TOML file such as:
+///
+/// ```toml
+/// default = "main"
+///
+/// [games.main]
+/// dir = "/home/me/Games/korabli/drive_c/Games/Korabli"
+///
+/// [games.test]
+/// dir = "/home/me/Games/korabli/drive_c/Games/Korabli_PT"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub default: Option<String>,
+  #[serde(default)]
+  pub games: BTreeMap<String, Game>,
+}
+
+impl Config {
+  pub fn default_path() -> PathBuf {
+    dirs::config_dir().join("config.toml")
+  }
+
+  /// Reads the config, which is empty when the file does not exist.
+  pub async fn load(path: &Path) -> Result<Self, Error> {
+    match fs::read_to_string(path).await {
+      Ok(content) => toml::from_str(&content).map_err(Error::DeToml),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(err) => Err(Error::Io(err)),
+    }
+  }
+
+  pub async fn save(&self, path: &Path) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).await.map_err(Error::Io)?;
+    }
+    fs::write(path, toml::to_string(self).map_err(Error::SerToml)?)
+      .await
+      .map_err(Error::Io)
+  }
+
+  /// The dir of the game called `name`, or of the default game when no name
+  /// is given. `None` when no name is given and there is no default.
+  pub fn game_dir(&self, name: Option<&str>) -> Result<Option<&Path>, Error> {
+    let Some(name) = name.or(self.default.as_deref()) else {
+      return Ok(None);
+    };
+    self
+      .games
+      .get(name)
+      .map(|game| Some(game.dir.as_path()))
+      .ok_or_else(|| Error::UnknownGame(name.to_owned()))
+  }
+
+  /// Registers a game, replacing any game of the same name. The first game
+  /// added becomes the default.
+  pub fn add_game(&mut self, name: &str, dir: PathBuf) {
+    self.games.insert(name.to_owned(), Game { dir });
+    if self.default.is_none() {
+      self.default = Some(name.to_owned());
+    }
+  }
+
+  pub fn remove_game(&mut self, name: &str) -> Result<Game, Error> {
+    let game = self
+      .games
+      .remove(name)
+      .ok_or_else(|| Error::UnknownGame(name.to_owned()))?;
+    if self.default.as_deref() == Some(name) {
+      self.default = None;
+    }
+    Ok(game)
+  }
+
+  pub fn set_default(&mut self, name: &str) -> Result<(), Error> {
+    if !self.games.contains_key(name) {
+      return Err(Error::UnknownGame(name.to_owned()));
+    }
+    self.default = Some(name.to_owned());
+    Ok(())
+  }
+}
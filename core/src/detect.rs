@@ -0,0 +1,253 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{dirs, game};
+
+/// What runs the Windows prefix a game was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Runner {
+  Wine,
+  Proton,
+  Lutris,
+  Bottles,
+}
+
+/// How a game was found inside its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FoundBy {
+  /// A directory with the `bin/<build>` layout in a usual install location.
+  Layout,
+  /// The install metadata of the Lesta Game Center.
+  GameCenter,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+  pub game_dir: PathBuf,
+  pub prefix: PathBuf,
+  pub runner: Runner,
+  pub found_by: FoundBy,
+}
+
+/// Directories of `drive_c` whose children are checked for a game install.
+const INSTALL_PARENTS: &[&str] = &["Games", "Program Files", "Program Files (x86)"];
+
+/// Looks for Mir Korabli installs in the Wine prefixes of the current user.
+///
+/// Only Linux is searched. Unreadable directories are skipped, as most of the
+/// searched locations do not exist on any given system.
+pub async fn detect() -> Vec<Candidate> {
+  let mut candidates: Vec<Candidate> = Vec::new();
+  if !cfg!(target_os = "linux") {
+    return candidates;
+  }
+
+  for (prefix, runner) in prefixes().await {
+    debug!("searching prefix {:?} ({:?})", prefix, runner);
+    let mut found = game_center_dirs(&prefix)
+      .await
+      .into_iter()
+      .map(|dir| (dir, FoundBy::GameCenter))
+      .collect::<Vec<_>>();
+    for parent in INSTALL_PARENTS {
+      for dir in subdirs(&prefix.join("drive_c").join(parent)).await {
+        found.push((dir, FoundBy::Layout));
+      }
+    }
+
+    for (game_dir, found_by) in found {
+      if game::find_res_mods_dir(&game_dir, None).await.is_err() {
+        continue;
+      }
+      let game_dir = fs::canonicalize(&game_dir).await.unwrap_or(game_dir);
+      if candidates.iter().any(|x| x.game_dir == game_dir) {
+        continue;
+      }
+      candidates.push(Candidate {
+        game_dir,
+        prefix: prefix.to_owned(),
+        runner,
+        found_by,
+      });
+    }
+  }
+  candidates
+}
+
+async fn prefixes() -> Vec<(PathBuf, Runner)> {
+  let mut prefixes = Vec::new();
+  let Some(home) = dirs::home_dir() else {
+    return prefixes;
+  };
+  let data_dir =
+    dirs::xdg_dir("XDG_DATA_HOME").unwrap_or_else(|| home.join(".local").join("share"));
+
+  if let Some(wine_prefix) = std::env::var_os("WINEPREFIX") {
+    prefixes.push((PathBuf::from(wine_prefix), Runner::Wine));
+  }
+  prefixes.push((home.join(".wine"), Runner::Wine));
+
+  for steam_dir in [
+    home.join(".steam").join("steam"),
+    data_dir.join("Steam"),
+    home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+  ] {
+    for library in steam_libraries(&steam_dir).await {
+      for compat_data in subdirs(&library.join("steamapps").join("compatdata")).await {
+        prefixes.push((compat_data.join("pfx"), Runner::Proton));
+      }
+    }
+  }
+
+  for config in files(&home.join(".config").join("lutris").join("games")).await {
+    if let Ok(content) = fs::read_to_string(&config).await {
+      for prefix in yaml_values(&content, "prefix") {
+        prefixes.push((PathBuf::from(prefix), Runner::Lutris));
+      }
+    }
+  }
+  for prefix in subdirs(&home.join("Games")).await {
+    prefixes.push((prefix, Runner::Lutris));
+  }
+
+  for bottles_dir in [
+    data_dir.join("bottles").join("bottles"),
+    home.join(".var/app/com.usebottles.bottles/data/bottles/bottles"),
+  ] {
+    for prefix in subdirs(&bottles_dir).await {
+      prefixes.push((prefix, Runner::Bottles));
+    }
+  }
+
+  let mut result: Vec<(PathBuf, Runner)> = Vec::new();
+  for (prefix, runner) in prefixes {
+    if !is_dir(&prefix.join("drive_c")).await {
+      continue;
+    }
+    let prefix = fs::canonicalize(&prefix).await.unwrap_or(prefix);
+    if !result.iter().any(|(x, _)| *x == prefix) {
+      result.push((prefix, runner));
+    }
+  }
+  result
+}
+
+/// The Steam library folders, including Steam's own directory.
+async fn steam_libraries(steam_dir: &Path) -> Vec<PathBuf> {
+  let mut libraries = vec![steam_dir.to_owned()];
+  let vdf = steam_dir.join("steamapps").join("libraryfolders.vdf");
+  if let Ok(content) = fs::read_to_string(vdf).await {
+    for line in content.lines() {
+      let mut fields = line.split('"').filter(|x| !x.trim().is_empty());
+      if let (Some("path"), Some(path)) = (fields.next(), fields.next()) {
+        libraries.push(PathBuf::from(path.replace("\\\\", "\\")));
+      }
+    }
+  }
+  libraries
+}
+
+/// Install paths the Lesta Game Center keeps in its `apps` directory, one
+/// file per install, and in the `working_dir` entries of its preferences.
+async fn game_center_dirs(prefix: &Path) -> Vec<PathBuf> {
+  let drive_c = prefix.join("drive_c");
+  let mut paths = Vec::new();
+
+  let apps_dir = drive_c
+    .join("ProgramData")
+    .join("Lesta")
+    .join("GameCenter")
+    .join("apps");
+  for app_dir in subdirs(&apps_dir).await {
+    for file in files(&app_dir).await {
+      if let Ok(content) = fs::read_to_string(&file).await {
+        paths.extend(content.lines().map(|x| x.trim().to_owned()));
+      }
+    }
+  }
+
+  for user_dir in subdirs(&drive_c.join("users")).await {
+    let preferences = user_dir.join("AppData/Roaming/Lesta/GameCenter/preferences.xml");
+    if let Ok(content) = fs::read_to_string(&preferences).await {
+      paths.extend(xml_values(&content, "working_dir"));
+    }
+  }
+
+  paths
+    .iter()
+    .filter_map(|path| windows_path(prefix, path))
+    .collect()
+}
+
+/// Maps a Windows path such as `C:\Games\Korabli` into `prefix`.
+fn windows_path(prefix: &Path, path: &str) -> Option<PathBuf> {
+  let mut chars = path.chars();
+  let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+  if chars.next() != Some(':') {
+    return None;
+  }
+  let drive = drive.to_ascii_lowercase();
+  let mut result = if drive == 'c' {
+    prefix.join("drive_c")
+  } else {
+    prefix.join("dosdevices").join(format!("{}:", drive))
+  };
+  for component in chars.as_str().split(['\\', '/']) {
+    if !component.is_empty() {
+      result.push(component);
+    }
+  }
+  Some(result)
+}
+
+/// Values of `key: value` lines, enough for the flat game configs of Lutris.
+fn yaml_values<'a>(content: &'a str, key: &'a str) -> impl Iterator<Item = &'a str> {
+  content.lines().filter_map(move |line| {
+    let (line_key, value) = line.trim().split_once(':')?;
+    let value = value.trim().trim_matches(['"', '\'']);
+    (line_key == key && !value.is_empty()).then_some(value)
+  })
+}
+
+/// Text of every `<tag>...</tag>` element.
+fn xml_values(content: &str, tag: &str) -> Vec<String> {
+  let open = format!("<{}>", tag);
+  let close = format!("</{}>", tag);
+  content
+    .split(open.as_str())
+    .skip(1)
+    .filter_map(|x| x.split_once(close.as_str()))
+    .map(|(value, _)| value.trim().to_owned())
+    .collect()
+}
+
+async fn is_dir(path: &Path) -> bool {
+  fs::metadata(path).await.is_ok_and(|x| x.is_dir())
+}
+
+async fn subdirs(dir: &Path) -> Vec<PathBuf> {
+  entries(dir, true).await
+}
+
+async fn files(dir: &Path) -> Vec<PathBuf> {
+  entries(dir, false).await
+}
+
+async fn entries(dir: &Path, want_dirs: bool) -> Vec<PathBuf> {
+  let mut entries = Vec::new();
+  let Ok(mut read_dir) = fs::read_dir(dir).await else {
+    return entries;
+  };
+  while let Ok(Some(entry)) = read_dir.next_entry().await {
+    let path = entry.path();
+    if is_dir(&path).await == want_dirs {
+      entries.push(path);
+    }
+  }
+  entries
+}
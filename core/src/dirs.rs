@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+/// The per-user cache directory of seamonkey.
+pub fn cache_dir() -> PathBuf {
+  let base = if cfg!(target_os = "windows") {
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+  } else if cfg!(target_os = "macos") {
+    home_dir().map(|home| home.join("Library").join("Caches"))
+  } else {
+    xdg_dir("XDG_CACHE_HOME").or_else(|| home_dir().map(|home| home.join(".cache")))
+  };
+  base.unwrap_or_else(std::env::temp_dir).join("seamonkey")
+}
+
+/// The per-user configuration directory of seamonkey.
+pub fn config_dir() -> PathBuf {
+  let base = if cfg!(target_os = "windows") {
+    std::env::var_os("APPDATA").map(PathBuf::from)
+  } else if cfg!(target_os = "macos") {
+    home_dir().map(|home| home.join("Library").join("Application Support"))
+  } else {
+    xdg_dir("XDG_CONFIG_HOME").or_else(|| home_dir().map(|home| home.join(".config")))
+  };
+  base.unwrap_or_else(std::env::temp_dir).join("seamonkey")
+}
+
+pub(crate) fn home_dir() -> Option<PathBuf> {
+  std::env::var_os("HOME").map(PathBuf::from)
+}
+
+/// XDG base directories must be absolute, relative ones are ignored.
+pub(crate) fn xdg_dir(var: &str) -> Option<PathBuf> {
+  std::env::var_os(var)
+    .map(PathBuf::from)
+    .filter(|dir| dir.is_absolute())
+}
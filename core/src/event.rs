@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+/// A question asked during installation when no policy answers it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Prompt {
+  MissingMetadata,
+  Existing,
+  Conflict,
+  Incompatible,
+}
+
+/// Something that happened while managing mods, reported to the [`Handler`].
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+  Message {
+    text: &'a str,
+  },
+  /// Never emitted by the core itself; frontends may use it to announce a question.
+  Prompt {
+    kind: Prompt,
+    text: &'a str,
+  },
+  /// One of the `count` items given to an install is about to be processed.
+  ItemStarted {
+    item: &'a str,
+    index: usize,
+    count: usize,
+  },
+  ItemFinished {
+    item: &'a str,
+    index: usize,
+    count: usize,
+  },
+  DownloadStarted {
+    url: &'a str,
+    total: Option<u64>,
+  },
+  DownloadProgress {
+    url: &'a str,
+    downloaded: u64,
+    total: Option<u64>,
+  },
+  DownloadFinished {
+    url: &'a str,
+    downloaded: u64,
+  },
+  ConflictDetected {
+    path: &'a Path,
+    installed: Vec<&'a str>,
+  },
+  FileExtracted {
+    path: &'a Path,
+  },
+  FileRepaired {
+    path: &'a Path,
+  },
+  ModInstalled {
+    install_id: &'a str,
+    id: Option<&'a str>,
+    version: Option<&'a str>,
+    from: &'a str,
+  },
+  ModUninstalled {
+    install_id: &'a str,
+    id: Option<&'a str>,
+  },
+  Error {
+    code: &'a str,
+    message: &'a str,
+  },
+  /// Never emitted by the core itself; frontends may use it to report what a
+  /// command found.
+  Result {
+    command: &'a str,
+    data: serde_json::Value,
+  },
+}
+
+/// Receives progress from the core and answers its questions.
+pub trait Handler: Send + Sync {
+  fn event(&self, event: &Event);
+
+  /// Asks a yes/no question, `default` being the answer to an empty reply.
+  fn confirm(&self, kind: Prompt, text: &str, default: bool) -> bool;
+
+  fn message(&self, text: &str) {
+    self.event(&Event::Message { text });
+  }
+}
@@ -0,0 +1,304 @@
+use std::path::{Component, Path, PathBuf};
+
+use async_zip::{error::ZipError, StoredZipEntry};
+use futures_lite::{io, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::fs;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("Zip: {0}")]
+  Zip(ZipError),
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("Absolute path: {0}")]
+  AbsolutePath(String),
+  #[error("Path traversal: {0}")]
+  PathTraversal(String),
+  #[error("Invalid path: {0}")]
+  InvalidPath(String),
+  #[error("Symlink entry: {0}")]
+  SymlinkEntry(String),
+  #[error("Too many entries: {0}")]
+  TooManyEntries(usize),
+  #[error("Too large: {0} bytes")]
+  TooLarge(u64),
+  #[error("Suspicious compression ratio of {0}: {1}")]
+  CompressionRatio(String, u64),
+  #[error("Entry larger than declared: {0}")]
+  SizeMismatch(String),
+  #[error("Escapes the target directory: {0}")]
+  EscapesTarget(PathBuf),
+}
+
+/// Entries accepted in one archive.
+pub const MAX_ENTRIES: usize = 65_536;
+/// Total uncompressed size accepted for one archive.
+pub const MAX_TOTAL_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+/// Expansion accepted for a single entry, as uncompressed size per compressed byte.
+pub const MAX_RATIO: u64 = 200;
+/// Entries smaller than this are not checked for their ratio, as tiny or
+/// highly repetitive files legitimately compress very well.
+const RATIO_CHECK_MIN_SIZE: u64 = 1024 * 1024;
+
+const UNIX_FILE_TYPE_MASK: u16 = 0o170000;
+const UNIX_SYMLINK: u16 = 0o120000;
+
+/// Turns the name of a zip entry into the relative path it is extracted to.
+///
+/// Each component is sanitized, and names which are absolute or climb out of
+/// the target directory are refused instead of being rewritten.
+pub fn entry_path(filename: &str) -> Result<PathBuf, Error> {
+  let filename_ = filename.replace('\\', "/");
+  if filename_.starts_with('/') {
+    return Err(Error::AbsolutePath(filename.to_owned()));
+  }
+
+  let mut path = PathBuf::new();
+  for (index, component) in filename_.split('/').enumerate() {
+    match component {
+      "" | "." => continue,
+      ".." => return Err(Error::PathTraversal(filename.to_owned())),
+      // A drive letter such as `C:`.
+      _ if index == 0 && component.ends_with(':') => {
+        return Err(Error::AbsolutePath(filename.to_owned()))
+      }
+      _ => {}
+    }
+    let sanitized = sanitize_filename::sanitize(component);
+    if sanitized.is_empty() || sanitized == "." || sanitized == ".." {
+      return Err(Error::InvalidPath(filename.to_owned()));
+    }
+    path.push(sanitized);
+  }
+
+  if path.components().all(|x| matches!(x, Component::Normal(_))) && path.components().count() > 0 {
+    Ok(path)
+  } else {
+    Err(Error::InvalidPath(filename.to_owned()))
+  }
+}
+
+/// Checks every entry of an archive before anything is extracted from it.
+pub fn check_entries(entries: &[StoredZipEntry]) -> Result<(), Error> {
+  if entries.len() > MAX_ENTRIES {
+    return Err(Error::TooManyEntries(entries.len()));
+  }
+
+  let mut total_size = 0u64;
+  for entry in entries {
+    let filename = entry.filename().as_str().map_err(Error::Zip)?;
+    entry_path(filename)?;
+    if entry
+      .unix_permissions()
+      .is_some_and(|mode| mode & UNIX_FILE_TYPE_MASK == UNIX_SYMLINK)
+    {
+      return Err(Error::SymlinkEntry(filename.to_owned()));
+    }
+
+    let size = entry.uncompressed_size();
+    total_size = total_size.saturating_add(size);
+    if total_size > MAX_TOTAL_SIZE {
+      return Err(Error::TooLarge(total_size));
+    }
+    if size >= RATIO_CHECK_MIN_SIZE {
+      let ratio = size / entry.compressed_size().max(1);
+      if ratio > MAX_RATIO {
+        return Err(Error::CompressionRatio(filename.to_owned(), ratio));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Makes sure `relative_path` stays inside `target_dir`, even when a part of
+/// it that already exists on disk is a symlink.
+pub async fn ensure_within(target_dir: &Path, relative_path: &Path) -> Result<(), Error> {
+  let target_dir = fs::canonicalize(target_dir).await.map_err(Error::Io)?;
+  let path = target_dir.join(relative_path);
+  for ancestor in path.ancestors() {
+    match fs::symlink_metadata(ancestor).await {
+      Ok(_) => {
+        let resolved = fs::canonicalize(ancestor).await.map_err(Error::Io)?;
+        return if resolved.starts_with(&target_dir) {
+          Ok(())
+        } else {
+          Err(Error::EscapesTarget(relative_path.to_owned()))
+        };
+      }
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+      Err(err) => return Err(Error::Io(err)),
+    }
+  }
+  Err(Error::EscapesTarget(relative_path.to_owned()))
+}
+
+/// Copies an entry, refusing to write more than its declared size.
+pub async fn copy(
+  reader: impl AsyncRead + Unpin,
+  mut writer: impl AsyncWrite + Unpin,
+  entry: &StoredZipEntry,
+) -> Result<u64, Error> {
+  let declared = entry.uncompressed_size();
+  let copied = io::copy(reader.take(declared + 1), &mut writer)
+    .await
+    .map_err(Error::Io)?;
+  writer.flush().await.map_err(Error::Io)?;
+  if copied > declared {
+    Err(Error::SizeMismatch(
+      entry.filename().as_str().unwrap_or_default().to_owned(),
+    ))
+  } else {
+    Ok(copied)
+  }
+}
+
+/// Reads an entry into memory, refusing more than its declared size.
+pub async fn read_to_end(
+  reader: impl AsyncRead + Unpin,
+  entry: &StoredZipEntry,
+) -> Result<Vec<u8>, Error> {
+  let mut buf = Vec::new();
+  copy(reader, &mut buf, entry).await?;
+  Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+  use async_zip::{base::read::mem::ZipFileReader, base::write::ZipFileWriter};
+  use async_zip::{Compression, ZipEntryBuilder};
+
+  use super::*;
+
+  /// Builds an archive in memory with one empty entry per `(name, mode)`.
+  async fn entries(files: &[(&str, u16)]) -> Vec<StoredZipEntry> {
+    let mut writer = ZipFileWriter::new(Vec::new());
+    for (filename, mode) in files {
+      let builder =
+        ZipEntryBuilder::new((*filename).into(), Compression::Stored).unix_permissions(*mode);
+      writer.write_entry_whole(builder, b"").await.unwrap();
+    }
+    let data = writer.close().await.unwrap();
+    ZipFileReader::new(data)
+      .await
+      .unwrap()
+      .file()
+      .entries()
+      .to_vec()
+  }
+
+  #[test]
+  fn entry_path_accepts_relative() {
+    assert_eq!(
+      entry_path("gui/flash/a.swf").unwrap(),
+      PathBuf::from("gui/flash/a.swf")
+    );
+    assert_eq!(
+      entry_path("./gui//a.swf").unwrap(),
+      PathBuf::from("gui/a.swf")
+    );
+    assert_eq!(
+      entry_path("gui\\a.swf").unwrap(),
+      PathBuf::from("gui/a.swf")
+    );
+  }
+
+  #[test]
+  fn entry_path_refuses_traversal() {
+    assert!(matches!(entry_path("../a"), Err(Error::PathTraversal(_))));
+    assert!(matches!(
+      entry_path("gui/../../a"),
+      Err(Error::PathTraversal(_))
+    ));
+    assert!(matches!(
+      entry_path("gui\\..\\..\\a"),
+      Err(Error::PathTraversal(_))
+    ));
+  }
+
+  #[test]
+  fn entry_path_refuses_absolute() {
+    assert!(matches!(
+      entry_path("/etc/passwd"),
+      Err(Error::AbsolutePath(_))
+    ));
+    assert!(matches!(
+      entry_path("\\Windows\\a"),
+      Err(Error::AbsolutePath(_))
+    ));
+    assert!(matches!(
+      entry_path("C:/Windows/a"),
+      Err(Error::AbsolutePath(_))
+    ));
+    assert!(matches!(
+      entry_path("C:\\Windows\\a"),
+      Err(Error::AbsolutePath(_))
+    ));
+  }
+
+  #[test]
+  fn entry_path_refuses_empty() {
+    assert!(matches!(entry_path(""), Err(Error::InvalidPath(_))));
+    assert!(matches!(entry_path("./"), Err(Error::InvalidPath(_))));
+  }
+
+  #[tokio::test]
+  async fn check_entries_accepts_files() {
+    check_entries(&entries(&[("gui/a.swf", 0o100644), ("b.txt", 0o100644)]).await).unwrap();
+  }
+
+  #[tokio::test]
+  async fn check_entries_refuses_symlinks() {
+    assert!(matches!(
+      check_entries(&entries(&[("a.txt", 0o100644), ("link", 0o120777)]).await),
+      Err(Error::SymlinkEntry(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn check_entries_refuses_bad_paths() {
+    assert!(matches!(
+      check_entries(&entries(&[("../a.txt", 0o100644)]).await),
+      Err(Error::PathTraversal(_))
+    ));
+    assert!(matches!(
+      check_entries(&entries(&[("C:\\a.txt", 0o100644)]).await),
+      Err(Error::AbsolutePath(_))
+    ));
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn ensure_within_follows_symlinks() {
+    let dir = std::env::temp_dir().join(format!("seamonkey-test-{}", uuid::Uuid::new_v4()));
+    let target_dir = dir.join("res_mods");
+    let outside = dir.join("outside");
+    fs::create_dir_all(target_dir.join("gui")).await.unwrap();
+    fs::create_dir_all(&outside).await.unwrap();
+    fs::symlink(&outside, target_dir.join("escape"))
+      .await
+      .unwrap();
+    fs::symlink(target_dir.join("gui"), target_dir.join("inside"))
+      .await
+      .unwrap();
+
+    let mut results = Vec::new();
+    for relative_path in [
+      "gui/a.swf",
+      "new/dir/a.swf",
+      "inside/a.swf",
+      "escape/a.swf",
+      "escape/new/a.swf",
+    ] {
+      results.push(ensure_within(&target_dir, Path::new(relative_path)).await);
+    }
+    fs::remove_dir_all(&dir).await.unwrap();
+    assert!(results[..3].iter().all(Result::is_ok), "{:?}", results);
+    assert!(
+      results[3..]
+        .iter()
+        .all(|res| matches!(res, Err(Error::EscapesTarget(_)))),
+      "{:?}",
+      results
+    );
+  }
+}
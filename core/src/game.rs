@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use serde::Serialize;
+use tokio::fs;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("Incorrect game directory structure")]
+  IncorrectGameDirectoryStructure,
+  #[error("Build not found: {0}")]
+  BuildNotFound(u64),
+}
+
+/// The version of one build of the game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GameVersion {
+  pub build: u64,
+  /// The client version from `game_info.xml`, such as `25.4.0.8753654`. Only
+  /// known for the newest build, which is the one the file describes.
+  pub client: Option<String>,
+}
+
+impl std::fmt::Display for GameVersion {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.client {
+      Some(client) => write!(f, "{}（{}）", client, self.build),
+      None => write!(f, "{}", self.build),
+    }
+  }
+}
+
+/// Entries of a build dir, one of which must exist for it to count as a build.
+const BUILD_MARKERS: &[&str] = &["res", "bin64"];
+
+/// The builds under `bin/`, oldest first.
+///
+/// Entries which are not numeric, or which do not look like a build of the
+/// game, are ignored.
+pub async fn builds(game_dir: &Path) -> Result<Vec<u64>, Error> {
+  let bin_dir = game_dir.join("bin");
+
+  debug!("bin_dir: {:?}", bin_dir);
+
+  if !fs::try_exists(bin_dir.as_path()).await.map_err(Error::Io)? {
+    return Err(Error::IncorrectGameDirectoryStructure);
+  }
+
+  let mut builds = Vec::new();
+  let mut read_dir = fs::read_dir(bin_dir.as_path()).await.map_err(Error::Io)?;
+  while let Some(entry) = read_dir.next_entry().await.map_err(Error::Io)? {
+    let Ok(build) = entry.file_name().to_string_lossy().parse::<u64>() else {
+      debug!("ignoring {:?}: not a build", entry.path());
+      continue;
+    };
+    if !is_build_dir(&entry.path()).await? {
+      debug!("ignoring {:?}: does not look like a build", entry.path());
+      continue;
+    }
+    builds.push(build);
+  }
+
+  debug!("versions: {:?}", builds);
+
+  builds.sort();
+  Ok(builds)
+}
+
+async fn is_build_dir(path: &Path) -> Result<bool, Error> {
+  for marker in BUILD_MARKERS {
+    if fs::metadata(path.join(marker))
+      .await
+      .is_ok_and(|x| x.is_dir())
+    {
+      return Ok(true);
+    }
+  }
+  Ok(false)
+}
+
+/// `build` when given and present, the newest build otherwise.
+pub async fn find_build(game_dir: &Path, build: Option<u64>) -> Result<u64, Error> {
+  let builds = builds(game_dir).await?;
+  match build {
+    Some(build) if builds.contains(&build) => Ok(build),
+    Some(build) => Err(Error::BuildNotFound(build)),
+    None => builds
+      .into_iter()
+      .max()
+      .ok_or(Error::IncorrectGameDirectoryStructure),
+  }
+}
+
+pub fn res_mods_dir(game_dir: &Path, build: u64) -> PathBuf {
+  game_dir
+    .join("bin")
+    .join(build.to_string())
+    .join("res_mods")
+}
+
+pub async fn find_res_mods_dir(game_dir: &Path, build: Option<u64>) -> Result<PathBuf, Error> {
+  Ok(res_mods_dir(game_dir, find_build(game_dir, build).await?))
+}
+
+pub async fn game_version(game_dir: &Path, build: u64) -> Result<GameVersion, Error> {
+  let client = if builds(game_dir).await?.last() == Some(&build) {
+    client_version(game_dir).await?
+  } else {
+    None
+  };
+  Ok(GameVersion { build, client })
+}
+
+/// The version of the build a `bin/<build>/res_mods` dir belongs to, `None`
+/// when the dir is not laid out that way.
+pub async fn version_of(res_mods_dir: &Path) -> Result<Option<GameVersion>, Error> {
+  let Some(build_dir) = res_mods_dir.parent() else {
+    return Ok(None);
+  };
+  let Some(build) = build_dir
+    .file_name()
+    .and_then(|x| x.to_str())
+    .and_then(|x| x.parse::<u64>().ok())
+  else {
+    return Ok(None);
+  };
+  let Some(game_dir) = build_dir
+    .parent()
+    .filter(|bin_dir| bin_dir.file_name().is_some_and(|x| x == "bin"))
+    .and_then(Path::parent)
+  else {
+    return Ok(None);
+  };
+  game_version(game_dir, build).await.map(Some)
+}
+
+/// Reads `<version name="client" installed="..."/>` from `game_info.xml`.
+async fn client_version(game_dir: &Path) -> Result<Option<String>, Error> {
+  let content = match fs::read_to_string(game_dir.join("game_info.xml")).await {
+    Ok(content) => content,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(err) => return Err(Error::Io(err)),
+  };
+  Ok(content.split("<version").skip(1).find_map(|element| {
+    let element = element.split('>').next()?;
+    if !element.contains(r#"name="client""#) {
+      return None;
+    }
+    let (_, rest) = element.split_once(r#"installed=""#)?;
+    rest.split('"').next().map(str::to_owned)
+  }))
+}
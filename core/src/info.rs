@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use log::debug;
+use serde::Serialize;
+
+use crate::{event::Handler, record};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("Record: {0}")]
+  Record(record::Error),
+  #[error("Mod not found: {0}")]
+  ModNotFound(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InfoItem {
+  pub install_id: String,
+  #[serde(flatten)]
+  pub record_item: record::RecordItem,
+}
+
+pub async fn info(
+  res_mods_dir: &Path,
+  item: &str,
+  handler: &dyn Handler,
+) -> Result<InfoItem, Error> {
+  debug!("info: {}", item);
+  let record = record::read_record(res_mods_dir, handler)
+    .await
+    .map_err(Error::Record)?;
+
+  record
+    .find(item)
+    .map(|(install_id, record_item)| InfoItem {
+      install_id: install_id.to_owned(),
+      record_item: record_item.to_owned(),
+    })
+    .ok_or_else(|| Error::ModNotFound(item.to_owned()))
+}
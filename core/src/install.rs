@@ -0,0 +1,1685 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use async_zip::error::ZipError;
+use futures_lite::StreamExt;
+use log::{debug, error, warn};
+use tokio::{
+  fs,
+  io::{AsyncBufRead, AsyncSeek, AsyncWriteExt, BufReader, BufWriter},
+};
+use tokio_util::compat::TokioAsyncWriteCompatExt;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+  cache::{self, Cache, Validator},
+  event::{Event, Handler, Prompt},
+  extract, game,
+  record::{self, METADATA_FILE},
+  signature::{self, Keyring},
+  transaction::Transaction,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("No Mod to install")]
+  NoModToInstall,
+  #[error("Unknown URL scheme")]
+  UnknownUrlScheme(String),
+  #[error("Record：{0}")]
+  Record(record::Error),
+  #[error("Zip：{0}")]
+  Zip(ZipError),
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("TOML：{0}")]
+  DeToml(toml::de::Error),
+  #[error("File conflict：{0}")]
+  FileConflict(PathBuf, Vec<FileConfilctCheck>),
+  #[error("Mod not found：{0}")]
+  ModNotFound(Url),
+  #[error("User interrupt")]
+  UserInterrupt,
+  #[error("Metadata not found")]
+  MetadataNotFound,
+  #[error("Already installed: {0} {1}")]
+  AlreadyInstalled(String, String),
+  #[error("Reqwest: {0}")]
+  Reqwest(reqwest::Error),
+  #[error("Empty request header: {0}")]
+  EmptyResponseHeader(String),
+  #[error("Invalid request header: {0}")]
+  InvalidRequestHeader(String, String),
+  #[error("Reqwest header to str: {0}")]
+  ReqwestHeaderToStr(reqwest::header::ToStrError),
+  #[error("Url parse: {0}")]
+  UrlParse(url::ParseError),
+  #[error("HTTP status {1}: {0}")]
+  HttpStatus(Url, u16),
+  #[error("Download incomplete: {0} ({1}/{2} bytes)")]
+  DownloadIncomplete(Url, u64, u64),
+  #[error("Cache: {0}")]
+  Cache(cache::Error),
+  #[error("Invalid sha256 checksum: {0}")]
+  InvalidChecksum(String),
+  #[error("Checksum mismatch: {0} (expected {1}, got {2})")]
+  ChecksumMismatch(String, String, String),
+  #[error("Signature: {0}")]
+  Signature(signature::Error),
+  #[error("Extract: {0}")]
+  Extract(extract::Error),
+  #[error("Unsigned archive: {0}")]
+  Unsigned(String),
+  #[error("Signer {0} is not an author of the mod: {1:?}")]
+  SignerNotAuthor(String, Vec<String>),
+  #[error("Unsigned archive claims an author of publisher {0}")]
+  UnsignedAuthor(String),
+  #[error("Game: {0}")]
+  Game(game::Error),
+  #[error("{0} requires game versions {1}, the game is at {2}")]
+  IncompatibleGameVersion(String, String, String),
+  #[error("Unmet dependencies of {0}: {1:?}")]
+  UnmetDependencies(String, Vec<record::Dependency>),
+  #[error("Dependency {1} of {0} has no update URL to install it from")]
+  DependencyWithoutSource(String, String),
+  #[error("Dependency cycle through {0}")]
+  DependencyCycle(String),
+  #[error("{0} conflicts with installed mods: {1:?}")]
+  DeclaredConflict(String, Vec<(String, String)>),
+  #[error("Installing {0} would break the dependencies of: {1:?}")]
+  BreaksDependents(String, Vec<(String, String)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OnConflict {
+  Abort,
+  /// Uninstall the mods which own the conflicting files. A conflicting file
+  /// which no mod owns still aborts the install.
+  Replace,
+  /// Like `Replace`, but also overwrite files which no mod owns.
+  Overwrite,
+  SkipFile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OnMissingMetadata {
+  Allow,
+  Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OnUnsigned {
+  Allow,
+  Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OnIncompatible {
+  Allow,
+  Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OnExisting {
+  Upgrade,
+  Keep,
+  Fail,
+}
+
+/// Answers to the questions asked during installation.
+///
+/// A policy left as `None` is asked on stdin, unless `yes_for_all` is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Flags {
+  pub yes_for_all: bool,
+  pub dry_run: bool,
+  pub on_conflict: Option<OnConflict>,
+  pub on_missing_metadata: Option<OnMissingMetadata>,
+  pub on_existing: Option<OnExisting>,
+  /// Unsigned archives are allowed unless this is `Deny`; it is never asked.
+  pub on_unsigned: Option<OnUnsigned>,
+  /// For mods whose `game_versions` exclude the build they are installed into.
+  pub on_incompatible: Option<OnIncompatible>,
+}
+
+impl Flags {
+  fn on_conflict(&self) -> Option<OnConflict> {
+    self
+      .on_conflict
+      .or(self.yes_for_all.then_some(OnConflict::Replace))
+  }
+
+  fn on_missing_metadata(&self) -> Option<OnMissingMetadata> {
+    self
+      .on_missing_metadata
+      .or(self.yes_for_all.then_some(OnMissingMetadata::Allow))
+  }
+
+  fn on_existing(&self) -> Option<OnExisting> {
+    self
+      .on_existing
+      .or(self.yes_for_all.then_some(OnExisting::Upgrade))
+  }
+
+  fn on_incompatible(&self) -> Option<OnIncompatible> {
+    self
+      .on_incompatible
+      .or(self.yes_for_all.then_some(OnIncompatible::Allow))
+  }
+
+  fn on_unsigned(&self) -> OnUnsigned {
+    self.on_unsigned.unwrap_or(OnUnsigned::Allow)
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Options<'a> {
+  pub warn_no_metadata: bool,
+  pub override_metadata: Option<record::Metadata>,
+  pub flags: Flags,
+  pub keyring: &'a Keyring,
+  /// The trusted publisher who signed the archive.
+  pub signed_by: Option<String>,
+  /// Where dependencies are downloaded to when they are installed first.
+  pub cache: &'a Cache,
+  pub resolving: &'a [String],
+}
+
+#[derive(Debug)]
+pub struct FileConfilctCheck {
+  pub installed: String,
+  pub metadata: Option<record::Metadata>,
+}
+
+pub async fn install(
+  res_mods_dir: &Path,
+  items: Vec<String>,
+  cache: &Cache,
+  keyring: &Keyring,
+  flags: Flags,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  let mut req_client = reqwest::Client::new();
+
+  debug!("install: {:?}", items);
+  if items.is_empty() {
+    Err(Error::NoModToInstall)
+  } else {
+    let count = items.len();
+    for (index, item) in items.iter().enumerate() {
+      handler.event(&Event::ItemStarted { item, index, count });
+      install_source(
+        res_mods_dir,
+        item,
+        cache,
+        &mut req_client,
+        keyring,
+        flags,
+        handler,
+        &[],
+      )
+      .await?;
+      handler.event(&Event::ItemFinished { item, index, count });
+    }
+    Ok(())
+  }
+}
+
+/// Installs an item, after the dependencies it declares. `resolving` holds
+/// the ids whose dependencies are being installed, to catch cycles.
+#[allow(clippy::too_many_arguments)]
+async fn install_source(
+  res_mods_dir: &Path,
+  item: &str,
+  cache: &Cache,
+  req_client: &mut reqwest::Client,
+  keyring: &Keyring,
+  flags: Flags,
+  handler: &dyn Handler,
+  resolving: &[String],
+) -> Result<(), Error> {
+  if let Ok(url) = item.parse::<Url>() {
+    match url.scheme() {
+      "file" => {
+        install_from_file(
+          res_mods_dir,
+          PathBuf::from(url.path()).as_ref(),
+          pinned_sha256(&url)?,
+          cache,
+          keyring,
+          flags,
+          handler,
+          resolving,
+        )
+        .await
+      }
+      "http" | "https" => {
+        install_from_web(
+          res_mods_dir,
+          &url,
+          cache,
+          req_client,
+          None,
+          Options {
+            warn_no_metadata: true,
+            override_metadata: None,
+            flags,
+            keyring,
+            signed_by: None,
+            cache,
+            resolving,
+          },
+          handler,
+        )
+        .await
+      }
+      "localizedkorabli" => match url.host() {
+        Some(host) if host.to_string().as_str() == "game" => {
+          install_gh_localized_korabli_game(
+            res_mods_dir,
+            cache,
+            req_client,
+            keyring,
+            flags,
+            handler,
+          )
+          .await
+        }
+        Some(_) | None => Err(Error::UnknownUrlScheme("localized_korabli".to_owned())),
+      },
+      scheme => Err(Error::UnknownUrlScheme(scheme.to_owned())),
+    }
+  } else {
+    install_from_file(
+      res_mods_dir,
+      PathBuf::from(item).as_ref(),
+      None,
+      cache,
+      keyring,
+      flags,
+      handler,
+      resolving,
+    )
+    .await
+  }
+}
+
+async fn install_gh_localized_korabli_game(
+  res_mods_dir: &Path,
+  cache: &Cache,
+  req_client: &mut reqwest::Client,
+  keyring: &Keyring,
+  flags: Flags,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  let test_client = reqwest::Client::builder()
+    .redirect(reqwest::redirect::Policy::none()) // 禁止重定向
+    .build()
+    .map_err(Error::Reqwest)?;
+  let res = test_client
+    .get("https://github.com/LocalizedKorabli/Korabli-LESTA-L10N/releases/latest")
+    .send()
+    .await
+    .map_err(Error::Reqwest)?;
+  if let Some(location) = res.headers().get("location") {
+    let location = location.to_str().map_err(Error::ReqwestHeaderToStr)?;
+    let location_url = Url::parse(location).map_err(Error::UrlParse)?;
+    let latest_version = location_url
+      .path_segments()
+      .and_then(|mut segments| segments.next_back());
+
+    if let Some(latest_version) = latest_version {
+      install_from_web(
+        res_mods_dir,
+        &Url::parse(&format!(
+          "https://github.com/LocalizedKorabli/Korabli-LESTA-L10N/releases/download/{}/{}.mod.zip",
+          latest_version, latest_version
+        ))
+        .map_err(Error::UrlParse)?,
+        cache,
+        req_client,
+        Some("localized_korabli_game".to_string()),
+        Options {
+          warn_no_metadata: false,
+          override_metadata: Some(record::Metadata {
+            id: "localized_korabli_game".to_string(),
+            name: "澪刻•战舰世界莱服本地化".to_string(),
+            description: "战舰世界俄服汉化（船舶世界；Мир Корабли；Мир кораблей；Mir Korabli；World of Warships；WOWS；莱服；毛服；LESTA；本地化；中文化；中文补丁）".to_string(),
+            authors: vec!["北斗余晖".to_string()],
+            url: "https://github.com/LocalizedKorabli/Korabli-LESTA-L10N".to_string(),
+            version: latest_version.to_string(),
+            update: "localizedkorabli://game".to_string(),
+            game_versions: None,
+            depends: Vec::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+          }),
+          flags,
+          keyring,
+          signed_by: None,
+          cache,
+          resolving: &[],
+        },
+        handler,
+      )
+      .await
+    } else {
+      Err(Error::InvalidRequestHeader(
+        "location".to_string(),
+        location.to_string(),
+      ))
+    }
+  } else {
+    Err(Error::EmptyResponseHeader("location".to_string()))
+  }
+}
+
+async fn install_from_web(
+  res_mods_dir: &Path,
+  mod_to_install: &Url,
+  cache: &Cache,
+  req_client: &mut reqwest::Client,
+  install_id: Option<String>,
+  mut options: Options<'_>,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  let expected_sha256 = match pinned_sha256(mod_to_install)? {
+    Some(expected_sha256) => Some(expected_sha256),
+    None => sidecar_sha256(mod_to_install, req_client).await,
+  };
+  let temp_file = download(
+    mod_to_install,
+    cache,
+    expected_sha256.as_deref(),
+    req_client,
+    handler,
+  )
+  .await?;
+  let sha256 = sha256::try_async_digest(&temp_file)
+    .await
+    .map_err(Error::Io)?;
+  if let Some(expected_sha256) = expected_sha256 {
+    if let Err(err) = check_sha256(mod_to_install, expected_sha256, &sha256, handler) {
+      cache.evict(mod_to_install).await.map_err(Error::Cache)?;
+      return Err(err);
+    }
+  }
+  let signature = fetch_sidecar(mod_to_install, SIGNATURE_SIDECAR_EXTENSION, req_client).await;
+  options.signed_by =
+    check_signature(&temp_file, signature, mod_to_install, &options, handler).await?;
+
+  install_zip(
+    res_mods_dir,
+    BufReader::new(fs::File::open(&temp_file).await.map_err(Error::Io)?),
+    mod_to_install.to_owned(),
+    sha256.to_owned(),
+    install_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+    options,
+    handler,
+  )
+  .await?;
+
+  Ok(())
+}
+
+const SHA256_FRAGMENT: &str = "sha256=";
+const SHA256_SIDECAR_EXTENSION: &str = "sha256";
+const SIGNATURE_SIDECAR_EXTENSION: &str = "minisig";
+
+/// The checksum pinned by a `#sha256=<hex>` fragment of `url`.
+fn pinned_sha256(url: &Url) -> Result<Option<String>, Error> {
+  url
+    .fragment()
+    .and_then(|fragment| fragment.strip_prefix(SHA256_FRAGMENT))
+    .map(|value| parse_sha256(value).ok_or_else(|| Error::InvalidChecksum(value.to_owned())))
+    .transpose()
+}
+
+/// The checksum published next to a remote archive as `<archive>.sha256`.
+async fn sidecar_sha256(url: &Url, req_client: &reqwest::Client) -> Option<String> {
+  let text = fetch_sidecar(url, SHA256_SIDECAR_EXTENSION, req_client).await?;
+  let sha256 = parse_sha256(&text);
+  if sha256.is_none() {
+    warn!("ignoring unreadable checksum sidecar of {}", url);
+  }
+  sha256
+}
+
+/// A file published next to a remote archive as `<archive>.<extension>`.
+///
+/// Sidecars are optional, so one which cannot be fetched is treated as
+/// missing. URLs with a query are not looked at, since the extension cannot
+/// be appended to their path.
+async fn fetch_sidecar(url: &Url, extension: &str, req_client: &reqwest::Client) -> Option<String> {
+  if url.query().is_some() {
+    debug!("no sidecar for {}, it has a query", url);
+    return None;
+  }
+  let mut sidecar_url = url.to_owned();
+  sidecar_url.set_fragment(None);
+  sidecar_url.set_path(&format!("{}.{}", url.path(), extension));
+
+  let res = match req_client.get(sidecar_url.to_owned()).send().await {
+    Ok(res) => res,
+    Err(err) => {
+      warn!("failed to fetch sidecar {}: {}", sidecar_url, err);
+      return None;
+    }
+  };
+  if !res.status().is_success() {
+    debug!("no sidecar at {}: {}", sidecar_url, res.status());
+    return None;
+  }
+  match res.text().await {
+    Ok(text) => Some(text),
+    Err(err) => {
+      warn!("failed to fetch sidecar {}: {}", sidecar_url, err);
+      None
+    }
+  }
+}
+
+/// A file next to a local archive, named `<archive>.<extension>`.
+async fn read_sidecar(path: &Path, extension: &str) -> Result<Option<String>, Error> {
+  let mut sidecar = path.as_os_str().to_owned();
+  sidecar.push(".");
+  sidecar.push(extension);
+  match fs::read_to_string(&sidecar).await {
+    Ok(text) => Ok(Some(text)),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(Error::Io(err)),
+  }
+}
+
+/// Checks the detached signature of an archive against the keyring, and
+/// returns the publisher who made it.
+///
+/// A signature which cannot be decoded, or which was not made by a trusted
+/// key, counts as no signature at all. Only a signature which a trusted key
+/// claims but does not match the archive is fatal.
+async fn check_signature(
+  archive: &Path,
+  signature: Option<String>,
+  from_url: &Url,
+  options: &Options<'_>,
+  handler: &dyn Handler,
+) -> Result<Option<String>, Error> {
+  if let Some(signature) = signature {
+    match options.keyring.verify(archive, &signature).await {
+      Ok(signer) => {
+        handler.message(&format!("签名校验通过：{}", signer));
+        return Ok(Some(signer.to_owned()));
+      }
+      Err(err @ (signature::Error::InvalidSignature(_) | signature::Error::UntrustedSignature)) => {
+        warn!("ignoring signature of {}: {}", from_url, err);
+      }
+      Err(err) => return Err(Error::Signature(err)),
+    }
+  }
+  match options.flags.on_unsigned() {
+    OnUnsigned::Allow => Ok(None),
+    OnUnsigned::Deny => Err(Error::Unsigned(from_url.to_string())),
+  }
+}
+
+/// Ties the claimed authors of a mod to the publishers trusted to sign for
+/// them: a signed mod must name an author of its signer, and an unsigned mod
+/// must not name the author of any trusted publisher.
+fn check_authors(metadata: Option<&record::Metadata>, options: &Options) -> Result<(), Error> {
+  let Some(metadata) = metadata else {
+    return Ok(());
+  };
+  match &options.signed_by {
+    Some(signer) => {
+      if options.keyring.publisher(signer).is_some_and(|publisher| {
+        publisher
+          .authors
+          .iter()
+          .any(|author| metadata.authors.contains(author))
+      }) {
+        Ok(())
+      } else {
+        Err(Error::SignerNotAuthor(
+          signer.to_owned(),
+          metadata.authors.to_owned(),
+        ))
+      }
+    }
+    None => match options.keyring.publishers_of(&metadata.authors).next() {
+      Some(publisher) => Err(Error::UnsignedAuthor(publisher.to_owned())),
+      None => Ok(()),
+    },
+  }
+}
+
+/// The dependencies of a mod which are not installed at a matching version.
+fn unmet_dependencies(
+  record: &record::Record,
+  metadata: &record::Metadata,
+) -> Vec<record::Dependency> {
+  metadata
+    .depends
+    .iter()
+    .filter(|dependency| {
+      !record.installed.values().any(|record_item| {
+        record_item.metadata.as_ref().is_some_and(|metadata_| {
+          (metadata_.id == dependency.id && dependency.matches(&metadata_.version))
+            || metadata_.provides.contains(&dependency.id)
+        })
+      })
+    })
+    .cloned()
+    .collect()
+}
+
+/// Installs the unmet dependencies of a mod from their update URLs.
+async fn install_dependencies(
+  res_mods_dir: &Path,
+  metadata: &record::Metadata,
+  unmet: &[record::Dependency],
+  options: &Options<'_>,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  if options.resolving.contains(&metadata.id) {
+    return Err(Error::DependencyCycle(metadata.id.to_owned()));
+  }
+  let mut resolving = options.resolving.to_vec();
+  resolving.push(metadata.id.to_owned());
+  let mut req_client = reqwest::Client::new();
+  for dependency in unmet {
+    let update = dependency.update.as_deref().ok_or_else(|| {
+      Error::DependencyWithoutSource(metadata.id.to_owned(), dependency.to_string())
+    })?;
+    handler.message(&format!("安装{}的依赖：{}", metadata.id, dependency));
+    Box::pin(install_source(
+      res_mods_dir,
+      update,
+      options.cache,
+      &mut req_client,
+      options.keyring,
+      options.flags,
+      handler,
+      &resolving,
+    ))
+    .await?;
+  }
+  Ok(())
+}
+
+/// Looks for installed mods which clash with `metadata` through their declared
+/// `conflicts` or `provides`, before anything is extracted, and offers to swap
+/// them out. Returns the install ids to uninstall, with the mod id and the
+/// capability of each clash.
+fn check_declared_conflicts(
+  record: &record::Record,
+  metadata: &record::Metadata,
+  upgraded: Option<&str>,
+  flags: &Flags,
+  handler: &dyn Handler,
+) -> Result<Vec<(String, String, String)>, Error> {
+  let mut clashes = record
+    .installed
+    .iter()
+    .filter(|(install_id, _)| Some(install_id.as_str()) != upgraded)
+    .filter_map(|(install_id, record_item)| {
+      let metadata_ = record_item.metadata.as_ref()?;
+      let through = metadata.clash_with(metadata_)?;
+      Some((install_id.to_owned(), metadata_.id.to_owned(), through))
+    })
+    .collect::<Vec<_>>();
+  if clashes.is_empty() {
+    return Ok(Vec::new());
+  }
+  clashes.sort_by(|a, b| a.1.cmp(&b.1));
+
+  let swap = match flags.on_conflict() {
+    Some(OnConflict::Replace | OnConflict::Overwrite) => true,
+    // Skipping files cannot resolve a declared conflict.
+    Some(OnConflict::Abort | OnConflict::SkipFile) => false,
+    None if flags.dry_run => true,
+    None => {
+      let mut text = format!("{}与以下已安装的Mod冲突：\n", metadata.id);
+      for (_, id, through) in clashes.iter() {
+        text += &format!("  - {}（{}）\n", id, through);
+      }
+      text += &format!("是否卸载它们并安装{}？[y/N]", metadata.id);
+      handler.confirm(Prompt::Conflict, &text, false)
+    }
+  };
+  if !swap {
+    return Err(Error::DeclaredConflict(
+      metadata.id.to_owned(),
+      clashes
+        .into_iter()
+        .map(|(_, id, through)| (id, through))
+        .collect(),
+    ));
+  }
+  Ok(clashes)
+}
+
+/// Warns about, or refuses, a mod whose `game_versions` exclude the build of
+/// the game it is installed into.
+async fn check_game_version(
+  res_mods_dir: &Path,
+  metadata: Option<&record::Metadata>,
+  flags: &Flags,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  let Some((metadata, game_versions)) =
+    metadata.and_then(|metadata| Some((metadata, metadata.game_versions.as_ref()?)))
+  else {
+    return Ok(());
+  };
+  let Some(version) = game::version_of(res_mods_dir).await.map_err(Error::Game)? else {
+    return Ok(());
+  };
+
+  match game_versions.contains(&version) {
+    Some(true) => Ok(()),
+    None => {
+      handler.message(&format!(
+        "无法确认{}是否兼容当前游戏版本{}，其要求的游戏版本为{}",
+        metadata.id, version, game_versions
+      ));
+      Ok(())
+    }
+    Some(false) => match flags.on_incompatible() {
+      Some(OnIncompatible::Allow) => {
+        handler.message(&format!(
+          "警告：{}要求的游戏版本为{}，与当前游戏版本{}不兼容，仍将安装",
+          metadata.id, game_versions, version
+        ));
+        Ok(())
+      }
+      Some(OnIncompatible::Deny) => Err(Error::IncompatibleGameVersion(
+        metadata.id.to_owned(),
+        game_versions.to_string(),
+        version.to_string(),
+      )),
+      None if flags.dry_run => {
+        handler.message(&format!(
+          "[演练] {}要求的游戏版本为{}，与当前游戏版本{}不兼容",
+          metadata.id, game_versions, version
+        ));
+        Ok(())
+      }
+      None => {
+        if handler.confirm(
+          Prompt::Incompatible,
+          &format!(
+            "{}要求的游戏版本为{}，与当前游戏版本{}不兼容，确认要安装吗？[y/N]",
+            metadata.id, game_versions, version
+          ),
+          false,
+        ) {
+          Ok(())
+        } else {
+          Err(Error::UserInterrupt)
+        }
+      }
+    },
+  }
+}
+
+/// Reads the hex digest at the start of a checksum, as written by `sha256sum`.
+fn parse_sha256(text: &str) -> Option<String> {
+  text
+    .split_whitespace()
+    .next()
+    .filter(|digest| digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()))
+    .map(|digest| digest.to_ascii_lowercase())
+}
+
+fn check_sha256(
+  url: &Url,
+  expected_sha256: String,
+  sha256: &str,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  if expected_sha256 == sha256 {
+    handler.message(&format!("SHA256校验通过：{}", sha256));
+    Ok(())
+  } else {
+    Err(Error::ChecksumMismatch(
+      url.to_string(),
+      expected_sha256,
+      sha256.to_owned(),
+    ))
+  }
+}
+
+/// Bytes downloaded between two progress events.
+const PROGRESS_STEP: u64 = 256 * 1024;
+/// Attempts made for one download before giving up.
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled after every further failure.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Why one download attempt stopped early.
+enum AttemptError {
+  /// The partial file is kept and the download resumed on the next attempt.
+  Retry(Error),
+  Fatal(Error),
+}
+
+/// Downloads `url` into the cache, or reuses an intact earlier download.
+///
+/// Without an expected checksum to check it against, an earlier download is
+/// only reused once the server confirms that the file has not changed since.
+///
+/// Data is written to a `.part` file first, which is resumed with a `Range`
+/// request after a dropped connection and only moved into the cache once
+/// complete. A part file left by an earlier run is only resumed with the
+/// `If-Range` validator it was downloaded with, so that it is started over
+/// when the file behind the URL has changed.
+pub async fn download(
+  url: &Url,
+  cache: &Cache,
+  expected_sha256: Option<&str>,
+  req_client: &mut reqwest::Client,
+  handler: &dyn Handler,
+) -> Result<PathBuf, Error> {
+  if let Some(cached) = cache
+    .lookup(url, expected_sha256)
+    .await
+    .map_err(Error::Cache)?
+  {
+    if expected_sha256.is_some() || is_unchanged(url, cache, req_client).await? {
+      handler.message(&format!("使用已缓存的下载：{}", url));
+      return Ok(cached);
+    }
+    debug!("cached download of {} is outdated", url);
+    cache.evict(url).await.map_err(Error::Cache)?;
+  }
+  let part_file = cache.part_path(url).await.map_err(Error::Cache)?;
+
+  let mut started = false;
+  let mut attempt = 1;
+  loop {
+    match download_attempt(url, cache, &part_file, req_client, &mut started, handler).await {
+      Ok(downloaded) => {
+        let cached = cache.store(url, &part_file).await.map_err(Error::Cache)?;
+        handler.event(&Event::DownloadFinished {
+          url: url.as_str(),
+          downloaded,
+        });
+        return Ok(cached);
+      }
+      Err(AttemptError::Retry(err)) if attempt < DOWNLOAD_ATTEMPTS => {
+        let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+        warn!("download of {} failed (attempt {}): {}", url, attempt, err);
+        handler.message(&format!(
+          "下载中断：{}，{}秒后重试（{}/{}）",
+          err,
+          delay.as_secs(),
+          attempt,
+          DOWNLOAD_ATTEMPTS - 1
+        ));
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+      }
+      Err(AttemptError::Retry(err) | AttemptError::Fatal(err)) => return Err(err),
+    }
+  }
+}
+
+/// Asks the server whether the cached download of `url` is still current,
+/// with the validator it was served with.
+async fn is_unchanged(
+  url: &Url,
+  cache: &Cache,
+  req_client: &reqwest::Client,
+) -> Result<bool, Error> {
+  let validator = cache.validator(url).await.map_err(Error::Cache)?;
+  if validator.is_empty() {
+    return Ok(false);
+  }
+  let mut req = req_client.get(url.to_owned());
+  if let Some(etag) = &validator.etag {
+    req = req.header(reqwest::header::IF_NONE_MATCH, etag);
+  }
+  if let Some(last_modified) = &validator.last_modified {
+    req = req.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+  }
+  match req.send().await {
+    Ok(res) => Ok(res.status() == reqwest::StatusCode::NOT_MODIFIED),
+    Err(err) => {
+      warn!(
+        "failed to revalidate the cached download of {}: {}",
+        url, err
+      );
+      Ok(false)
+    }
+  }
+}
+
+/// Continues the download in `part_file` from its current length, and
+/// returns the full size once the body has been received completely.
+async fn download_attempt(
+  url: &Url,
+  cache: &Cache,
+  part_file: &Path,
+  req_client: &mut reqwest::Client,
+  started: &mut bool,
+  handler: &dyn Handler,
+) -> Result<u64, AttemptError> {
+  let offset = match fs::metadata(part_file).await {
+    Ok(metadata) => metadata.len(),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+    Err(err) => return Err(AttemptError::Fatal(Error::Io(err))),
+  };
+  let validator = cache
+    .part_validator(url)
+    .await
+    .map_err(|err| AttemptError::Fatal(Error::Cache(err)))?;
+
+  let mut req = req_client.get(url.to_owned());
+  match validator.as_ref().and_then(Validator::if_range) {
+    Some(validator) if offset > 0 => {
+      req = req
+        .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+        .header(reqwest::header::IF_RANGE, validator);
+    }
+    // Without a validator, only a part file written earlier in this run is
+    // known to belong to the same file.
+    None if offset > 0 && *started => {
+      req = req.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    _ => {}
+  }
+  let res = req
+    .send()
+    .await
+    .map_err(|err| AttemptError::Retry(Error::Reqwest(err)))?;
+
+  let status = res.status();
+  let resumed = status == reqwest::StatusCode::PARTIAL_CONTENT;
+  if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+    // The part file does not match what the server has, start over.
+    fs::remove_file(part_file)
+      .await
+      .map_err(|err| AttemptError::Fatal(Error::Io(err)))?;
+    return Err(AttemptError::Retry(Error::HttpStatus(
+      url.to_owned(),
+      status.as_u16(),
+    )));
+  } else if status == reqwest::StatusCode::NOT_FOUND {
+    return Err(AttemptError::Fatal(Error::ModNotFound(url.to_owned())));
+  } else if status.is_server_error() {
+    return Err(AttemptError::Retry(Error::HttpStatus(
+      url.to_owned(),
+      status.as_u16(),
+    )));
+  } else if !status.is_success() {
+    return Err(AttemptError::Fatal(Error::HttpStatus(
+      url.to_owned(),
+      status.as_u16(),
+    )));
+  }
+
+  if !resumed {
+    cache
+      .set_part_validator(url, &response_validator(&res))
+      .await
+      .map_err(|err| AttemptError::Fatal(Error::Cache(err)))?;
+  }
+
+  let mut downloaded = if resumed { offset } else { 0 };
+  let total = if resumed {
+    content_range_total(&res).or(res.content_length().map(|len| len + offset))
+  } else {
+    res.content_length()
+  };
+  if !*started {
+    handler.event(&Event::DownloadStarted {
+      url: url.as_str(),
+      total,
+    });
+    *started = true;
+  }
+
+  let file = fs::OpenOptions::new()
+    .create(true)
+    .write(true)
+    .append(resumed)
+    .truncate(!resumed)
+    .open(part_file)
+    .await
+    .map_err(|err| AttemptError::Fatal(Error::Io(err)))?;
+  let mut writer = BufWriter::new(file);
+  let mut stream = res.bytes_stream();
+  let mut reported = downloaded;
+  let result = async {
+    while let Some(chunk) = stream.next().await {
+      let chunk = chunk.map_err(|err| AttemptError::Retry(Error::Reqwest(err)))?;
+      writer
+        .write_all(&chunk)
+        .await
+        .map_err(|err| AttemptError::Fatal(Error::Io(err)))?;
+      downloaded += chunk.len() as u64;
+      if downloaded - reported >= PROGRESS_STEP {
+        handler.event(&Event::DownloadProgress {
+          url: url.as_str(),
+          downloaded,
+          total,
+        });
+        reported = downloaded;
+      }
+    }
+    Ok(())
+  }
+  .await;
+  // Keep whatever arrived before a failure, so the next attempt can resume.
+  writer
+    .flush()
+    .await
+    .map_err(|err| AttemptError::Fatal(Error::Io(err)))?;
+  result?;
+
+  match total {
+    Some(total) if downloaded != total => Err(AttemptError::Retry(Error::DownloadIncomplete(
+      url.to_owned(),
+      downloaded,
+      total,
+    ))),
+    _ => Ok(downloaded),
+  }
+}
+
+/// The validator a download is served with.
+fn response_validator(res: &reqwest::Response) -> Validator {
+  let header = |name| {
+    res
+      .headers()
+      .get(name)
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_owned)
+  };
+  Validator {
+    etag: header(reqwest::header::ETAG),
+    last_modified: header(reqwest::header::LAST_MODIFIED),
+  }
+}
+
+/// The full size from a `Content-Range: bytes <start>-<end>/<size>` header.
+fn content_range_total(res: &reqwest::Response) -> Option<u64> {
+  res
+    .headers()
+    .get(reqwest::header::CONTENT_RANGE)?
+    .to_str()
+    .ok()?
+    .rsplit_once('/')?
+    .1
+    .parse()
+    .ok()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn install_from_file(
+  res_mods_dir: &Path,
+  mod_to_install: &Path,
+  expected_sha256: Option<String>,
+  cache: &Cache,
+  keyring: &Keyring,
+  flags: Flags,
+  handler: &dyn Handler,
+  resolving: &[String],
+) -> Result<(), Error> {
+  let from_url = Url::from_file_path(if mod_to_install.is_absolute() {
+    mod_to_install.to_string_lossy().to_string()
+  } else {
+    std::env::current_dir()
+      .expect("wtf current dir")
+      .join(mod_to_install)
+      .to_string_lossy()
+      .to_string()
+  })
+  .expect("wtf file url");
+
+  if !fs::try_exists(&mod_to_install).await.map_err(Error::Io)? {
+    return Err(Error::ModNotFound(from_url.to_owned()));
+  }
+
+  let expected_sha256 = match expected_sha256 {
+    Some(expected_sha256) => Some(expected_sha256),
+    None => match read_sidecar(mod_to_install, SHA256_SIDECAR_EXTENSION).await? {
+      Some(text) => {
+        let sha256 = parse_sha256(&text);
+        if sha256.is_none() {
+          warn!(
+            "ignoring unreadable checksum sidecar of {:?}",
+            mod_to_install
+          );
+        }
+        sha256
+      }
+      None => None,
+    },
+  };
+  let sha256 = sha256::try_async_digest(mod_to_install)
+    .await
+    .map_err(Error::Io)?;
+  if let Some(expected_sha256) = expected_sha256 {
+    check_sha256(&from_url, expected_sha256, &sha256, handler)?;
+  }
+
+  let mut options = Options {
+    warn_no_metadata: true,
+    override_metadata: None,
+    flags,
+    keyring,
+    signed_by: None,
+    cache,
+    resolving,
+  };
+  let signature = read_sidecar(mod_to_install, SIGNATURE_SIDECAR_EXTENSION).await?;
+  options.signed_by =
+    check_signature(mod_to_install, signature, &from_url, &options, handler).await?;
+
+  install_zip(
+    res_mods_dir,
+    BufReader::new(fs::File::open(mod_to_install).await.map_err(Error::Io)?),
+    from_url,
+    sha256,
+    Uuid::new_v4().to_string(),
+    options,
+    handler,
+  )
+  .await
+}
+
+async fn install_zip(
+  res_mods_dir: &Path,
+  mod_to_install: impl AsyncBufRead + AsyncSeek + Unpin,
+  from_url: Url,
+  sha256: String,
+  install_id: String,
+  options: Options<'_>,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  let mut record = record::read_record(res_mods_dir, handler)
+    .await
+    .map_err(Error::Record)?;
+
+  let mut mod_to_install_zip =
+    async_zip::tokio::read::seek::ZipFileReader::with_tokio(mod_to_install)
+      .await
+      .map_err(Error::Zip)?;
+  extract::check_entries(mod_to_install_zip.file().entries()).map_err(Error::Extract)?;
+
+  let mut record_item = record::RecordItem {
+    sha256,
+    last_update_time: chrono::Local::now().to_string(),
+    from: from_url.to_string(),
+    files: mod_to_install_zip
+      .file()
+      .entries()
+      .iter()
+      .filter_map(|x| match x.dir() {
+        Ok(true) => None,
+        Ok(false) => Some(
+          x.filename()
+            .as_str()
+            .map_err(Error::Zip)
+            .and_then(|filename| extract::entry_path(filename).map_err(Error::Extract)),
+        ),
+        Err(err) => Some(Err(Error::Zip(err))),
+      })
+      // The metadata is kept in the record, extracting it would make every
+      // two mods conflict.
+      .filter(|x| !x.as_ref().is_ok_and(|x| x == Path::new(METADATA_FILE)))
+      .collect::<Result<Vec<_>, _>>()?,
+    file_hashes: HashMap::new(),
+    signed_by: options.signed_by.to_owned(),
+    metadata: {
+      if let Some(override_metadata) = &options.override_metadata {
+        Some(override_metadata.to_owned())
+      } else if let Some((index, _)) =
+        mod_to_install_zip
+          .file()
+          .entries()
+          .iter()
+          .enumerate()
+          .find(|(_, file)| {
+            file
+              .filename()
+              .as_str()
+              .map(|filename| filename == METADATA_FILE)
+              .unwrap_or(false)
+          })
+      {
+        Some({
+          let entry = mod_to_install_zip.file().entries()[index].to_owned();
+          let reader = mod_to_install_zip
+            .reader_without_entry(index)
+            .await
+            .map_err(Error::Zip)?;
+          let buf = extract::read_to_end(reader, &entry)
+            .await
+            .map_err(Error::Extract)?;
+          toml::from_str(&String::from_utf8_lossy(&buf)).map_err(Error::DeToml)?
+        })
+      } else {
+        if options.warn_no_metadata {
+          warn!("metadata not found");
+          match options.flags.on_missing_metadata() {
+            Some(OnMissingMetadata::Allow) => {}
+            Some(OnMissingMetadata::Deny) => return Err(Error::MetadataNotFound),
+            None if options.flags.dry_run => {}
+            None => {
+              if !handler.confirm(
+                Prompt::MissingMetadata,
+                "未找到元数据，确认要安装吗？[Y/n]",
+                true,
+              ) {
+                return Err(Error::UserInterrupt);
+              }
+            }
+          }
+        }
+        None
+      }
+    },
+  };
+
+  check_authors(record_item.metadata.as_ref(), &options)?;
+
+  check_game_version(
+    res_mods_dir,
+    record_item.metadata.as_ref(),
+    &options.flags,
+    handler,
+  )
+  .await?;
+
+  let existing = record_item.metadata.as_ref().and_then(|metadata| {
+    record
+      .installed
+      .iter()
+      .find_map(|(install_id_, record_item_)| {
+        record_item_
+          .metadata
+          .as_ref()
+          .filter(|metadata_| metadata.id == metadata_.id)
+          .map(|metadata_| {
+            (
+              install_id_.to_owned(),
+              metadata_.to_owned(),
+              metadata.to_owned(),
+            )
+          })
+      })
+  });
+
+  let to_remove = match existing {
+    Some((install_id_, metadata_, metadata)) => match options.flags.on_existing() {
+      Some(OnExisting::Upgrade) => Some(install_id_),
+      Some(OnExisting::Keep) => {
+        handler.message(&format!(
+          "已安装{}，版本{}，跳过安装版本{}",
+          metadata_.id, metadata_.version, metadata.version
+        ));
+        return Ok(());
+      }
+      Some(OnExisting::Fail) => {
+        return Err(Error::AlreadyInstalled(metadata_.id, metadata_.version));
+      }
+      None if options.flags.dry_run => Some(install_id_),
+      None => {
+        if !handler.confirm(
+          Prompt::Existing,
+          &format!(
+            "检测到已安装的{}，版本{}，将要安装版本{}，是否升级？[Y/n]",
+            metadata_.id, metadata_.version, metadata.version
+          ),
+          true,
+        ) {
+          return Err(Error::UserInterrupt);
+        }
+        Some(install_id_)
+      }
+    },
+    None => None,
+  };
+
+  let clashes = match &record_item.metadata {
+    Some(metadata) => check_declared_conflicts(
+      &record,
+      metadata,
+      to_remove.as_deref(),
+      &options.flags,
+      handler,
+    )?,
+    None => Vec::new(),
+  };
+  let swapped = clashes
+    .iter()
+    .map(|(install_id, _, _)| install_id.to_owned())
+    .collect::<Vec<_>>();
+
+  check_dependents(
+    &record,
+    &to_remove
+      .iter()
+      .chain(swapped.iter())
+      .cloned()
+      .collect::<Vec<_>>(),
+    &install_id,
+    &record_item,
+  )?;
+
+  // Dependencies are installed only once nothing about the mod itself can
+  // refuse it, so that a refusal leaves no dependency behind.
+  if let Some(metadata) = &record_item.metadata {
+    let unmet = unmet_dependencies(&record, metadata);
+    if options.flags.dry_run {
+      for dependency in unmet.iter() {
+        handler.message(&format!(
+          "[演练] 将先安装依赖{}：{}",
+          dependency,
+          dependency.update.as_deref().unwrap_or("未提供安装地址")
+        ));
+      }
+    } else if !unmet.is_empty() {
+      install_dependencies(res_mods_dir, metadata, &unmet, &options, handler).await?;
+      record = record::read_record(res_mods_dir, handler)
+        .await
+        .map_err(Error::Record)?;
+      // Say installed at a version which still does not match.
+      let unmet = unmet_dependencies(&record, metadata);
+      if !unmet.is_empty() {
+        return Err(Error::UnmetDependencies(metadata.id.to_owned(), unmet));
+      }
+      check_dependents(
+        &record,
+        &to_remove
+          .iter()
+          .chain(swapped.iter())
+          .cloned()
+          .collect::<Vec<_>>(),
+        &install_id,
+        &record_item,
+      )?;
+    }
+  }
+
+  if options.flags.dry_run {
+    return print_install_plan(
+      res_mods_dir,
+      &record,
+      &record_item,
+      &install_id,
+      to_remove.as_deref(),
+      &swapped,
+      options.flags.on_conflict(),
+      handler,
+    )
+    .await;
+  }
+
+  if let Some(metadata) = &record_item.metadata {
+    for (_, id, through) in clashes.iter() {
+      handler.message(&format!("卸载与{}冲突的{}（{}）", metadata.id, id, through));
+    }
+  }
+
+  let mut transaction = Transaction::begin(res_mods_dir).await.map_err(Error::Io)?;
+
+  let res = async {
+    for to_remove in to_remove.iter().chain(swapped.iter()) {
+      if let Some(removed) = record.installed.remove(to_remove) {
+        for file in removed.files.iter() {
+          transaction.remove_file(file).await.map_err(Error::Io)?;
+        }
+      }
+    }
+
+    let mut conflicts = Vec::new();
+    for file_path in record_item.files.iter() {
+      if fs::try_exists(res_mods_dir.join(file_path))
+        .await
+        .map_err(Error::Io)?
+      {
+        conflicts.push(file_path.to_owned());
+      }
+    }
+
+    for file_path in conflicts.iter() {
+      let check_list = conflict_check_list(&record, file_path);
+      handler.event(&Event::ConflictDetected {
+        path: file_path,
+        installed: check_list
+          .iter()
+          .map(|check| check.installed.as_str())
+          .collect(),
+      });
+    }
+
+    if !conflicts.is_empty() {
+      let on_conflict = match options.flags.on_conflict() {
+        Some(on_conflict) => on_conflict,
+        None => {
+          let mut text = String::new();
+          for file_path in conflicts.iter() {
+            text += &format!("要安装的Mod与已有的Mod发生文件冲突：{:?}\n", file_path);
+            for check in conflict_check_list(&record, file_path) {
+              if let Some(metadata) = &check.metadata {
+                text += &format!(
+                  "  - {}({}), 来自{}\n",
+                  metadata.name, metadata.id, metadata.url
+                );
+              } else {
+                text += &format!("  - {}, 元数据未找到\n", check.installed);
+              }
+            }
+            if conflict_check_list(&record, file_path).is_empty() {
+              text += "  - 不属于任何Mod，不会被覆盖\n";
+            }
+          }
+          text += "是否卸载冲突的所有Mod并覆盖冲突的文件？[y/N]";
+          if handler.confirm(Prompt::Conflict, &text, false) {
+            OnConflict::Replace
+          } else {
+            OnConflict::Abort
+          }
+        }
+      };
+
+      match on_conflict {
+        OnConflict::Abort => {
+          let check_list = conflict_check_list(&record, &conflicts[0]);
+          return Err(Error::FileConflict(conflicts.swap_remove(0), check_list));
+        }
+        OnConflict::Replace | OnConflict::Overwrite => {
+          if on_conflict == OnConflict::Replace {
+            if let Some(index) = conflicts
+              .iter()
+              .position(|file_path| conflict_check_list(&record, file_path).is_empty())
+            {
+              return Err(Error::FileConflict(
+                conflicts.swap_remove(index),
+                Vec::new(),
+              ));
+            }
+          }
+          let replaced = conflicts
+            .iter()
+            .flat_map(|file_path| conflict_check_list(&record, file_path))
+            .map(|check| check.installed)
+            .collect::<Vec<_>>();
+          check_dependents(&record, &replaced, &install_id, &record_item)?;
+          for file_path in conflicts.iter() {
+            for check in conflict_check_list(&record, file_path) {
+              if let Some(removed) = record.installed.remove(&check.installed) {
+                for file in removed.files.iter() {
+                  transaction.remove_file(file).await.map_err(Error::Io)?;
+                }
+              }
+            }
+            transaction
+              .remove_file(file_path)
+              .await
+              .map_err(Error::Io)?;
+          }
+        }
+        OnConflict::SkipFile => {
+          for file_path in conflicts.iter() {
+            handler.message(&format!("跳过冲突的文件：{}", file_path.to_string_lossy()));
+          }
+          record_item
+            .files
+            .retain(|file_path| !conflicts.contains(file_path));
+        }
+      }
+    }
+    debug!("ready for install updated mod");
+
+    for (index, file) in mod_to_install_zip
+      .file()
+      .entries()
+      .to_vec()
+      .iter()
+      .enumerate()
+    {
+      let file_path = extract::entry_path(file.filename().as_str().map_err(Error::Zip)?)
+        .map_err(Error::Extract)?;
+      extract::ensure_within(res_mods_dir, &file_path)
+        .await
+        .map_err(Error::Extract)?;
+      if file.dir().map_err(Error::Zip)? {
+        transaction
+          .create_dir_all(&file_path)
+          .await
+          .map_err(Error::Io)?;
+      } else if record_item.files.contains(&file_path) {
+        let reader = mod_to_install_zip
+          .reader_without_entry(index)
+          .await
+          .map_err(Error::Zip)?;
+        let writer = transaction
+          .create_file(&file_path)
+          .await
+          .map_err(Error::Io)?
+          .compat_write();
+        extract::copy(reader, writer, file)
+          .await
+          .map_err(Error::Extract)?;
+        record_item.file_hashes.insert(
+          file_path.to_owned(),
+          record::FileHash::of(&res_mods_dir.join(&file_path))
+            .await
+            .map_err(Error::Io)?,
+        );
+        handler.event(&Event::FileExtracted { path: &file_path });
+      }
+    }
+
+    record.installed.insert(install_id.to_owned(), record_item);
+
+    record::write_record(res_mods_dir, &record)
+      .await
+      .map_err(Error::Record)?;
+
+    let installed = &record.installed[&install_id];
+    handler.event(&Event::ModInstalled {
+      install_id: &install_id,
+      id: installed.metadata.as_ref().map(|x| x.id.as_str()),
+      version: installed.metadata.as_ref().map(|x| x.version.as_str()),
+      from: &installed.from,
+    });
+    Ok(())
+  }
+  .await;
+
+  match res {
+    Ok(()) => {
+      transaction.commit().await;
+      Ok(())
+    }
+    Err(err) => {
+      if let Err(rollback_err) = transaction.rollback().await {
+        error!("rollback failed: {}", rollback_err);
+        handler.message(&format!("安装失败且回滚未能完成：{}", rollback_err));
+      }
+      Err(err)
+    }
+  }
+}
+
+/// Refuses to uninstall or upgrade mods which others depend on: every
+/// dependency met in `record` must still be met once the mods in `removed`
+/// are uninstalled and `record_item` is installed as `install_id`.
+fn check_dependents(
+  record: &record::Record,
+  removed: &[String],
+  install_id: &str,
+  record_item: &record::RecordItem,
+) -> Result<(), Error> {
+  let mut after = record.to_owned();
+  for removed in removed {
+    after.installed.remove(removed);
+  }
+  after
+    .installed
+    .insert(install_id.to_owned(), record_item.to_owned());
+
+  let mut broken = after
+    .installed
+    .iter()
+    .filter(|(install_id_, _)| *install_id_ != install_id)
+    .filter_map(|(_, record_item_)| {
+      let metadata = record_item_.metadata.as_ref()?;
+      let unmet = unmet_dependencies(record, metadata);
+      let dependency = unmet_dependencies(&after, metadata)
+        .into_iter()
+        .find(|dependency| !unmet.contains(dependency))?;
+      Some((metadata.id.to_owned(), dependency.to_string()))
+    })
+    .collect::<Vec<_>>();
+  if broken.is_empty() {
+    return Ok(());
+  }
+  broken.sort();
+  Err(Error::BreaksDependents(
+    record_item
+      .metadata
+      .as_ref()
+      .map_or_else(|| record_item.from.to_owned(), |x| x.id.to_owned()),
+    broken,
+  ))
+}
+
+fn conflict_check_list(record: &record::Record, file_path: &Path) -> Vec<FileConfilctCheck> {
+  record
+    .installed
+    .iter()
+    .filter(|(_, record)| {
+      record
+        .files
+        .iter()
+        .any(|record_file_path| record_file_path == file_path)
+    })
+    .map(|(installed, record)| FileConfilctCheck {
+      installed: installed.to_owned(),
+      metadata: record.metadata.to_owned(),
+    })
+    .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn print_install_plan(
+  res_mods_dir: &Path,
+  record: &record::Record,
+  record_item: &record::RecordItem,
+  install_id: &str,
+  to_remove: Option<&str>,
+  swapped: &[String],
+  on_conflict: Option<OnConflict>,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  match &record_item.metadata {
+    Some(metadata) => handler.message(&format!(
+      "[演练] 将安装{}({})，版本{}，来自{}",
+      metadata.name, metadata.id, metadata.version, record_item.from
+    )),
+    None => handler.message(&format!(
+      "[演练] 将安装{}（未找到元数据）",
+      record_item.from
+    )),
+  }
+
+  let removed = to_remove.and_then(|to_remove| record.installed.get(to_remove));
+  if let Some(removed) = removed {
+    if let Some(metadata) = &removed.metadata {
+      handler.message(&format!(
+        "[演练] 将升级已安装的{}，版本{}",
+        metadata.id, metadata.version
+      ));
+    }
+    for file in removed.files.iter() {
+      if !record_item.files.contains(file) {
+        handler.message(&format!("[演练] 将删除：{}", file.to_string_lossy()));
+      }
+    }
+  }
+
+  let swapped_ids = swapped;
+  let swapped = swapped
+    .iter()
+    .filter_map(|install_id| record.installed.get(install_id))
+    .collect::<Vec<_>>();
+  for removed in swapped.iter() {
+    if let Some(metadata) = &removed.metadata {
+      handler.message(&format!(
+        "[演练] 将卸载与之冲突的{}({})",
+        metadata.name, metadata.id
+      ));
+    }
+    for file in removed.files.iter() {
+      if !record_item.files.contains(file) {
+        handler.message(&format!("[演练] 将删除：{}", file.to_string_lossy()));
+      }
+    }
+  }
+
+  let mut replaced_owners = Vec::new();
+  for file_path in record_item.files.iter() {
+    let replaced = removed
+      .into_iter()
+      .chain(swapped.iter().copied())
+      .any(|removed| removed.files.contains(file_path));
+    if replaced {
+      handler.message(&format!("[演练] 将替换：{}", file_path.to_string_lossy()));
+    } else if fs::try_exists(res_mods_dir.join(file_path))
+      .await
+      .map_err(Error::Io)?
+    {
+      let owned = !conflict_check_list(record, file_path).is_empty();
+      let action = match on_conflict {
+        Some(OnConflict::Abort) => "，将中止安装",
+        Some(OnConflict::Replace) if !owned => "，该文件不属于任何Mod，将中止安装",
+        Some(OnConflict::Replace) => "，将卸载冲突的Mod并覆盖",
+        Some(OnConflict::Overwrite) => "，将卸载冲突的Mod并覆盖",
+        Some(OnConflict::SkipFile) => "，将跳过该文件",
+        None => "",
+      };
+      handler.message(&format!(
+        "[演练] 文件冲突{}：{}",
+        action,
+        file_path.to_string_lossy()
+      ));
+      for check in conflict_check_list(record, file_path) {
+        if let Some(metadata) = &check.metadata {
+          handler.message(&format!("  - {}({})", metadata.name, metadata.id));
+        } else {
+          handler.message(&format!("  - {}, 元数据未找到", check.installed));
+        }
+        replaced_owners.push(check.installed);
+      }
+    } else {
+      handler.message(&format!("[演练] 将创建：{}", file_path.to_string_lossy()));
+    }
+  }
+
+  if matches!(
+    on_conflict,
+    Some(OnConflict::Replace | OnConflict::Overwrite)
+  ) && !replaced_owners.is_empty()
+  {
+    replaced_owners.extend(to_remove.map(str::to_owned));
+    replaced_owners.extend(swapped_ids.iter().cloned());
+    check_dependents(record, &replaced_owners, install_id, record_item)?;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,22 @@
+pub mod cache;
+pub mod config;
+pub mod detect;
+pub mod dirs;
+pub mod event;
+pub mod extract;
+pub mod game;
+pub mod info;
+pub mod install;
+pub mod list;
+pub mod lock;
+mod manager;
+pub mod migrate;
+pub mod record;
+pub mod repair;
+pub mod signature;
+mod transaction;
+pub mod uninstall;
+pub mod update;
+pub mod verify;
+
+pub use manager::{Error, ModManager};
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use log::debug;
+use serde::Serialize;
+
+use crate::{
+  event::Handler,
+  game,
+  record::{self, GameVersions},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("Record: {0}")]
+  Record(record::Error),
+  #[error("Game: {0}")]
+  Game(game::Error),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListItem {
+  pub install_id: String,
+  pub id: Option<String>,
+  pub name: Option<String>,
+  pub version: Option<String>,
+  pub from: String,
+  pub last_update_time: String,
+  pub file_count: usize,
+  pub game_versions: Option<GameVersions>,
+  /// Whether the mod supports the build it is installed into, `None` when
+  /// that cannot be told.
+  pub compatible: Option<bool>,
+}
+
+pub async fn list(res_mods_dir: &Path, handler: &dyn Handler) -> Result<Vec<ListItem>, Error> {
+  let record = record::read_record(res_mods_dir, handler)
+    .await
+    .map_err(Error::Record)?;
+
+  let game_version = game::version_of(res_mods_dir).await.map_err(Error::Game)?;
+
+  let mut items = record
+    .installed
+    .into_iter()
+    .map(|(install_id, record_item)| {
+      let game_versions = record_item
+        .metadata
+        .as_ref()
+        .and_then(|x| x.game_versions.to_owned());
+      let compatible = game_versions
+        .as_ref()
+        .zip(game_version.as_ref())
+        .and_then(|(game_versions, game_version)| game_versions.contains(game_version));
+      ListItem {
+        install_id,
+        id: record_item.metadata.as_ref().map(|x| x.id.to_owned()),
+        name: record_item.metadata.as_ref().map(|x| x.name.to_owned()),
+        version: record_item.metadata.as_ref().map(|x| x.version.to_owned()),
+        from: record_item.from,
+        last_update_time: record_item.last_update_time,
+        file_count: record_item.files.len(),
+        game_versions,
+        compatible,
+      }
+    })
+    .collect::<Vec<_>>();
+  items.sort_by(|a, b| (&a.id, &a.install_id).cmp(&(&b.id, &b.install_id)));
+
+  debug!("list: {:?}", items);
+
+  Ok(items)
+}
@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use fs4::tokio::AsyncFileExt;
+use log::debug;
+use tokio::{fs, io::AsyncWriteExt};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("Another seamonkey is running")]
+  Locked,
+}
+
+/// An advisory lock on `res_mods`, released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+  _file: fs::File,
+}
+
+/// Locks `res_mods`, creating it first as a newly updated build has none yet.
+pub async fn acquire(res_mods_dir: &Path) -> Result<Lock, Error> {
+  let lock_file = res_mods_dir.join(".seamonkey.lock");
+  debug!("lock: {:?}", lock_file);
+  fs::create_dir_all(res_mods_dir).await.map_err(Error::Io)?;
+  let mut file = fs::OpenOptions::new()
+    .create(true)
+    .truncate(false)
+    .write(true)
+    .open(&lock_file)
+    .await
+    .map_err(Error::Io)?;
+  if !file.try_lock_exclusive().map_err(Error::Io)? {
+    return Err(Error::Locked);
+  }
+  file.set_len(0).await.map_err(Error::Io)?;
+  file
+    .write_all(std::process::id().to_string().as_bytes())
+    .await
+    .map_err(Error::Io)?;
+  file.flush().await.map_err(Error::Io)?;
+  Ok(Lock { _file: file })
+}
@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+  cache::Cache,
+  event::Handler,
+  info, install, list, lock,
+  migrate::{self, MigrateReport},
+  repair,
+  signature::Keyring,
+  uninstall, update,
+  verify::{self, Problem},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("Lock: {0}")]
+  Lock(lock::Error),
+  #[error("Install: {0}")]
+  Install(install::Error),
+  #[error("Uninstall: {0}")]
+  Uninstall(uninstall::Error),
+  #[error("Update: {0}")]
+  Update(update::Error),
+  #[error("List: {0}")]
+  List(list::Error),
+  #[error("Info: {0}")]
+  Info(info::Error),
+  #[error("Verify: {0}")]
+  Verify(verify::Error),
+  #[error("Repair: {0}")]
+  Repair(repair::Error),
+  #[error("Migrate: {0}")]
+  Migrate(migrate::Error),
+}
+
+/// Manages the mods installed into one `res_mods` directory.
+///
+/// Mutating operations hold the `res_mods` lock for their whole duration,
+/// except when only planning a dry run.
+pub struct ModManager {
+  res_mods_dir: PathBuf,
+  cache: Cache,
+  keyring: Keyring,
+  handler: Box<dyn Handler>,
+}
+
+impl ModManager {
+  pub fn new(
+    res_mods_dir: PathBuf,
+    cache: Cache,
+    keyring: Keyring,
+    handler: Box<dyn Handler>,
+  ) -> Self {
+    Self {
+      res_mods_dir,
+      cache,
+      keyring,
+      handler,
+    }
+  }
+
+  pub fn res_mods_dir(&self) -> &Path {
+    &self.res_mods_dir
+  }
+
+  pub fn handler(&self) -> &dyn Handler {
+    self.handler.as_ref()
+  }
+
+  async fn lock(&self, dry_run: bool) -> Result<Option<lock::Lock>, Error> {
+    if dry_run {
+      Ok(None)
+    } else {
+      lock::acquire(&self.res_mods_dir)
+        .await
+        .map(Some)
+        .map_err(Error::Lock)
+    }
+  }
+
+  pub async fn install(&self, items: Vec<String>, flags: install::Flags) -> Result<(), Error> {
+    let _lock = self.lock(flags.dry_run).await?;
+    install::install(
+      &self.res_mods_dir,
+      items,
+      &self.cache,
+      &self.keyring,
+      flags,
+      self.handler.as_ref(),
+    )
+    .await
+    .map_err(Error::Install)
+  }
+
+  pub async fn uninstall(
+    &self,
+    items: Vec<String>,
+    cascade: bool,
+    dry_run: bool,
+  ) -> Result<(), Error> {
+    let _lock = self.lock(dry_run).await?;
+    uninstall::uninstall(
+      &self.res_mods_dir,
+      items,
+      cascade,
+      dry_run,
+      self.handler.as_ref(),
+    )
+    .await
+    .map_err(Error::Uninstall)
+  }
+
+  pub async fn update(&self, flags: install::Flags) -> Result<(), Error> {
+    let _lock = self.lock(flags.dry_run).await?;
+    update::update(
+      &self.res_mods_dir,
+      &self.cache,
+      &self.keyring,
+      flags,
+      self.handler.as_ref(),
+    )
+    .await
+    .map_err(Error::Update)
+  }
+
+  pub async fn repair(&self, item: &str, dry_run: bool) -> Result<(), Error> {
+    let _lock = self.lock(dry_run).await?;
+    repair::repair(
+      &self.res_mods_dir,
+      item,
+      &self.cache,
+      dry_run,
+      self.handler.as_ref(),
+    )
+    .await
+    .map_err(Error::Repair)
+  }
+
+  /// Carries the mods installed in the `res_mods` dir of an older build into
+  /// the managed one.
+  pub async fn migrate(
+    &self,
+    from: &Path,
+    mode: migrate::Mode,
+    flags: install::Flags,
+  ) -> Result<MigrateReport, Error> {
+    let _lock = self.lock(flags.dry_run).await?;
+    migrate::migrate(
+      from,
+      &self.res_mods_dir,
+      mode,
+      &self.cache,
+      &self.keyring,
+      flags,
+      self.handler.as_ref(),
+    )
+    .await
+    .map_err(Error::Migrate)
+  }
+
+  pub async fn list(&self) -> Result<Vec<list::ListItem>, Error> {
+    list::list(&self.res_mods_dir, self.handler.as_ref())
+      .await
+      .map_err(Error::List)
+  }
+
+  pub async fn info(&self, item: &str) -> Result<info::InfoItem, Error> {
+    info::info(&self.res_mods_dir, item, self.handler.as_ref())
+      .await
+      .map_err(Error::Info)
+  }
+
+  pub async fn verify(&self, items: &[String]) -> Result<Vec<Problem>, Error> {
+    verify::check(&self.res_mods_dir, items, self.handler.as_ref())
+      .await
+      .map_err(Error::Verify)
+  }
+}
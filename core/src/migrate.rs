@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{
+  cache::Cache,
+  event::Handler,
+  game, install,
+  record::{self, FileHash, RecordItem},
+  signature::Keyring,
+  transaction::Transaction,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("Record: {0}")]
+  Record(record::Error),
+  #[error("Game: {0}")]
+  Game(game::Error),
+  #[error("Cannot migrate a res_mods dir into itself")]
+  SameDirectory,
+}
+
+/// How mods are carried into the new build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Mode {
+  /// Copy the installed files, which works offline.
+  #[default]
+  Copy,
+  /// Install every mod again from where it came from.
+  Reinstall,
+}
+
+/// Why a mod was not carried into the new build.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "reason", content = "detail")]
+pub enum NotApplied {
+  /// Files which are missing or modified in the old build, so there is
+  /// nothing trustworthy to copy.
+  FilesMissing(Vec<PathBuf>),
+  /// Files which the new build already ships, so the mod's own versions
+  /// would clash with the game.
+  FilesShadowed(Vec<PathBuf>),
+  ReinstallFailed(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrateItem {
+  pub install_id: String,
+  pub id: Option<String>,
+  pub name: Option<String>,
+  /// `None` when the mod was migrated.
+  pub not_applied: Option<NotApplied>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrateReport {
+  pub from: PathBuf,
+  pub to: PathBuf,
+  pub items: Vec<MigrateItem>,
+}
+
+/// The `res_mods` dir of a build older than `to` which still holds the
+/// installed mods, when `to` has none installed yet.
+pub async fn pending(
+  game_dir: &Path,
+  to: u64,
+  handler: &dyn Handler,
+) -> Result<Option<PathBuf>, Error> {
+  let builds = game::builds(game_dir).await.map_err(Error::Game)?;
+  let to_record = record::read_record(&game::res_mods_dir(game_dir, to), handler)
+    .await
+    .map_err(Error::Record)?;
+  if !to_record.installed.is_empty() {
+    return Ok(None);
+  }
+
+  for build in builds.into_iter().rev().filter(|build| *build < to) {
+    let res_mods_dir = game::res_mods_dir(game_dir, build);
+    if !fs::try_exists(&res_mods_dir).await.map_err(Error::Io)? {
+      continue;
+    }
+    let record = record::read_record(&res_mods_dir, handler)
+      .await
+      .map_err(Error::Record)?;
+    if !record.installed.is_empty() {
+      return Ok(Some(res_mods_dir));
+    }
+  }
+  Ok(None)
+}
+
+/// Carries the mods installed in `from` into `to`. The old build is left as
+/// it is, the game no longer reads it.
+///
+/// Mods already recorded in `to` are skipped, so an interrupted migration can
+/// simply be run again. A reinstalled mod gets a new install id, so it is
+/// recognized by its `Metadata.id`, or by where it came from when it has no
+/// metadata.
+pub async fn migrate(
+  from: &Path,
+  to: &Path,
+  mode: Mode,
+  cache: &Cache,
+  keyring: &Keyring,
+  flags: install::Flags,
+  handler: &dyn Handler,
+) -> Result<MigrateReport, Error> {
+  debug!("migrate: {:?} -> {:?} ({:?})", from, to, mode);
+  // Only a dry run can find `to` missing, it is not `from` then.
+  if fs::try_exists(to).await.map_err(Error::Io)?
+    && fs::canonicalize(from).await.map_err(Error::Io)?
+      == fs::canonicalize(to).await.map_err(Error::Io)?
+  {
+    return Err(Error::SameDirectory);
+  }
+
+  let old_record = record::read_record(from, handler)
+    .await
+    .map_err(Error::Record)?;
+  let mut new_record = record::read_record(to, handler)
+    .await
+    .map_err(Error::Record)?;
+
+  let mut installed = old_record.installed.into_iter().collect::<Vec<_>>();
+  installed.sort_by(|(_, a), (_, b)| a.last_update_time.cmp(&b.last_update_time));
+
+  let mut items = Vec::new();
+  let mut to_copy = Vec::new();
+  for (install_id, record_item) in installed {
+    let mut item = MigrateItem {
+      install_id: install_id.to_owned(),
+      id: record_item.metadata.as_ref().map(|x| x.id.to_owned()),
+      name: record_item.metadata.as_ref().map(|x| x.name.to_owned()),
+      not_applied: None,
+    };
+    if is_migrated(&new_record, &install_id, &record_item) {
+      debug!("{} is already migrated", install_id);
+      items.push(item);
+      continue;
+    }
+
+    item.not_applied = match mode {
+      Mode::Copy => check_copy(from, to, &record_item).await?,
+      Mode::Reinstall if flags.dry_run => {
+        handler.message(&format!("[演练] 将重新安装：{}", record_item.from));
+        None
+      }
+      Mode::Reinstall => install::install(
+        to,
+        vec![record_item.from.to_owned()],
+        cache,
+        keyring,
+        flags,
+        handler,
+      )
+      .await
+      .err()
+      .map(|err| NotApplied::ReinstallFailed(err.to_string())),
+    };
+    if mode == Mode::Copy && item.not_applied.is_none() {
+      to_copy.push((install_id, record_item));
+    }
+    items.push(item);
+  }
+
+  if mode == Mode::Copy {
+    if flags.dry_run {
+      for (_, record_item) in to_copy.iter() {
+        for file in record_item.files.iter() {
+          handler.message(&format!("[演练] 将复制：{}", file.to_string_lossy()));
+        }
+      }
+    } else if !to_copy.is_empty() {
+      fs::create_dir_all(to).await.map_err(Error::Io)?;
+      let mut transaction = Transaction::begin(to).await.map_err(Error::Io)?;
+      match copy(from, &mut transaction, &to_copy).await {
+        Ok(()) => {
+          new_record.installed.extend(to_copy);
+          match record::write_record(to, &new_record).await {
+            Ok(()) => transaction.commit().await,
+            Err(err) => {
+              transaction.rollback().await.map_err(Error::Io)?;
+              return Err(Error::Record(err));
+            }
+          }
+        }
+        Err(err) => {
+          transaction.rollback().await.map_err(Error::Io)?;
+          return Err(err);
+        }
+      }
+    }
+  }
+
+  Ok(MigrateReport {
+    from: from.to_owned(),
+    to: to.to_owned(),
+    items,
+  })
+}
+
+/// Whether `to_record` already holds the mod, copied under the same install id
+/// or reinstalled under a new one.
+fn is_migrated(to_record: &record::Record, install_id: &str, record_item: &RecordItem) -> bool {
+  to_record.installed.contains_key(install_id)
+    || to_record.installed.values().any(|record_item_| {
+      match (&record_item.metadata, &record_item_.metadata) {
+        (Some(metadata), Some(metadata_)) => metadata.id == metadata_.id,
+        (None, None) => record_item.from == record_item_.from,
+        _ => false,
+      }
+    })
+}
+
+/// Whether the files of a mod can be copied as they are.
+async fn check_copy(
+  from: &Path,
+  to: &Path,
+  record_item: &RecordItem,
+) -> Result<Option<NotApplied>, Error> {
+  let mut missing = Vec::new();
+  let mut shadowed = Vec::new();
+  for file in record_item.files.iter() {
+    let old_path = from.join(file);
+    if !fs::try_exists(&old_path).await.map_err(Error::Io)? {
+      missing.push(file.to_owned());
+      continue;
+    }
+    // Records written before hashes were kept cannot tell a modified file.
+    if let Some(expected) = record_item.file_hashes.get(file) {
+      if FileHash::of(&old_path).await.map_err(Error::Io)? != *expected {
+        missing.push(file.to_owned());
+        continue;
+      }
+    }
+    if fs::try_exists(to.join(file)).await.map_err(Error::Io)? {
+      shadowed.push(file.to_owned());
+    }
+  }
+
+  Ok(if !missing.is_empty() {
+    Some(NotApplied::FilesMissing(missing))
+  } else if !shadowed.is_empty() {
+    Some(NotApplied::FilesShadowed(shadowed))
+  } else {
+    None
+  })
+}
+
+async fn copy(
+  from: &Path,
+  transaction: &mut Transaction,
+  to_copy: &[(String, RecordItem)],
+) -> Result<(), Error> {
+  for (_, record_item) in to_copy {
+    for file in record_item.files.iter() {
+      let mut reader = fs::File::open(from.join(file)).await.map_err(Error::Io)?;
+      let mut writer = transaction.create_file(file).await.map_err(Error::Io)?;
+      tokio::io::copy(&mut reader, &mut writer)
+        .await
+        .map_err(Error::Io)?;
+      writer.sync_all().await.map_err(Error::Io)?;
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,660 @@
+use std::{
+  cmp::Ordering,
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+  fs,
+  io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::{event::Handler, game::GameVersion};
+
+/// The schema version written by this build of seamonkey.
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// The metadata of a mod, at the root of its archive. It is kept in the record
+/// rather than extracted, since every two mods would conflict over it.
+pub const METADATA_FILE: &str = "seamonkey.toml";
+
+/// Upgrades a record from the schema version at its index to the next one,
+/// collecting the files under `res_mods` which no mod owns any more.
+const MIGRATIONS: &[fn(&mut serde_json::Value, &mut Vec<PathBuf>)] =
+  &[migrate_v0_to_v1, migrate_v1_to_v2];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Record {
+  pub schema_version: u64,
+  pub installed: HashMap<String, RecordItem>,
+  /// Files under `res_mods` which no mod owns any more since the record was
+  /// migrated, removed by the next [`write_record`].
+  #[serde(skip)]
+  pub disowned: Vec<PathBuf>,
+}
+
+impl Default for Record {
+  fn default() -> Self {
+    Self {
+      schema_version: SCHEMA_VERSION,
+      installed: HashMap::new(),
+      disowned: Vec::new(),
+    }
+  }
+}
+
+impl Record {
+  /// Looks up an installed mod by install id, falling back to `Metadata.id`.
+  pub fn find(&self, item: &str) -> Option<(&String, &RecordItem)> {
+    self.installed.get_key_value(item).or_else(|| {
+      self.installed.iter().find(|(_, record)| {
+        record
+          .metadata
+          .as_ref()
+          .is_some_and(|metadata| metadata.id == item)
+      })
+    })
+  }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordItem {
+  pub sha256: String,
+  pub last_update_time: String,
+  pub files: Vec<PathBuf>,
+  pub file_hashes: HashMap<PathBuf, FileHash>,
+  pub from: String,
+  pub metadata: Option<Metadata>,
+  /// The trusted publisher who signed the archive.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub signed_by: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileHash {
+  pub sha256: String,
+  pub size: u64,
+}
+
+impl FileHash {
+  pub async fn of(path: &Path) -> Result<Self, std::io::Error> {
+    let size = fs::metadata(path).await?.len();
+    let sha256 = sha256::try_async_digest(path).await?;
+    Ok(Self { sha256, size })
+  }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Metadata {
+  pub id: String,
+  pub name: String,
+  pub description: String,
+  pub version: String,
+  pub authors: Vec<String>,
+  pub url: String,
+  pub update: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub game_versions: Option<GameVersions>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub depends: Vec<Dependency>,
+  /// Ids of mods, or capabilities, which cannot be installed alongside.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub conflicts: Vec<String>,
+  /// Virtual capabilities such as `crew-voice`. Only one installed mod may
+  /// provide a capability, and a dependency may name one instead of a mod id.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub provides: Vec<String>,
+}
+
+impl Metadata {
+  /// The mod id or capability through which two mods clash, as declared by
+  /// either of them.
+  pub fn clash_with(&self, other: &Metadata) -> Option<String> {
+    let declared = |a: &Metadata, b: &Metadata| {
+      a.conflicts
+        .iter()
+        .find(|x| **x == b.id || b.provides.contains(x))
+        .cloned()
+    };
+    declared(self, other)
+      .or_else(|| declared(other, self))
+      .or_else(|| {
+        self
+          .provides
+          .iter()
+          .find(|x| other.provides.contains(x))
+          .cloned()
+      })
+  }
+}
+
+/// Another mod which must be installed first.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Dependency {
+  pub id: String,
+  /// A requirement on its version such as `>=1.2, <2`, any version when left
+  /// out. A bare version means `^` and `*` any version, as in Cargo.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub version: Option<String>,
+  /// Where to install it from when it is missing or too old.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub update: Option<String>,
+}
+
+impl Dependency {
+  pub fn matches(&self, version: &str) -> bool {
+    let Some(requirement) = &self.version else {
+      return true;
+    };
+    let version = parse_lenient_version(version);
+    requirement
+      .split(',')
+      .map(str::trim)
+      .filter(|x| !x.is_empty())
+      .all(|comparator| matches_comparator(comparator, &version))
+  }
+}
+
+impl std::fmt::Display for Dependency {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.version {
+      Some(version) => write!(f, "{} {}", self.id, version),
+      None => write!(f, "{}", self.id),
+    }
+  }
+}
+
+fn matches_comparator(comparator: &str, version: &[u64]) -> bool {
+  // Wildcards as in Cargo, `*` is any version and `1.2.*` is `~1.2`.
+  if comparator == "*" {
+    return true;
+  }
+  if let Some(prefix) = comparator.strip_suffix(".*") {
+    return matches_comparator(&format!("~{}", prefix.trim_start_matches('=')), version);
+  }
+  let (op, bound) = match comparator.find(|c: char| c.is_ascii_digit()) {
+    Some(index) => comparator.split_at(index),
+    None => return false,
+  };
+  let bound = parse_lenient_version(bound);
+  let cmp = compare_versions(version, &bound);
+  match op.trim() {
+    ">=" => cmp.is_ge(),
+    "<=" => cmp.is_le(),
+    ">" => cmp.is_gt(),
+    "<" => cmp.is_lt(),
+    "=" => cmp.is_eq(),
+    // Only the last given component may grow, `~1.2` is `>=1.2, <1.3`.
+    "~" => cmp.is_ge() && compare_versions(version, &bump(&bound, bound.len())).is_lt(),
+    // The leftmost non-zero component may not change, `^0.3` is `>=0.3, <0.4`.
+    "^" | "" => {
+      let significant = bound
+        .iter()
+        .position(|x| *x != 0)
+        .map_or(bound.len(), |x| x + 1);
+      cmp.is_ge() && compare_versions(version, &bump(&bound, significant)).is_lt()
+    }
+    _ => false,
+  }
+}
+
+/// `version` cut to `len` components with the last one incremented.
+fn bump(version: &[u64], len: usize) -> Vec<u64> {
+  let mut bumped = version[..len.min(version.len())].to_vec();
+  if let Some(last) = bumped.last_mut() {
+    *last += 1;
+  }
+  bumped
+}
+
+/// Compares dotted versions, missing components counting as zero.
+fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
+  (0..a.len().max(b.len()))
+    .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+    .find(|x| x.is_ne())
+    .unwrap_or(Ordering::Equal)
+}
+
+/// Reads the leading digits of every dotted component, so that `1.2.0-beta`
+/// is `1.2.0`.
+fn parse_lenient_version(version: &str) -> Vec<u64> {
+  version
+    .trim()
+    .trim_start_matches(['v', 'V'])
+    .split('.')
+    .map(|x| {
+      x.chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .unwrap_or(0)
+    })
+    .collect()
+}
+
+/// The game versions a mod works with, both ends included.
+///
+/// `min` and `max` are client versions such as `"25.4"`, which cover every
+/// version starting with them, so `max = "25"` allows all of 25.x.
+/// `min_build` and `max_build` are build numbers under `bin/`, such as
+/// `8753654`, for mods tied to particular builds.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GameVersions {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_build: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_build: Option<u64>,
+}
+
+impl GameVersions {
+  /// Whether `version` is in range, `None` when a bound cannot be checked
+  /// because the client version is unknown or the bound is malformed.
+  pub fn contains(&self, version: &GameVersion) -> Option<bool> {
+    let checks = [
+      self
+        .min
+        .as_ref()
+        .map(|min| compare(version, min).map(Ordering::is_ge)),
+      self
+        .max
+        .as_ref()
+        .map(|max| compare(version, max).map(Ordering::is_le)),
+      self
+        .min_build
+        .map(|min_build| Some(version.build >= min_build)),
+      self
+        .max_build
+        .map(|max_build| Some(version.build <= max_build)),
+    ];
+    // A bound which is known to fail decides, even when another is unknown.
+    let mut contains = Some(true);
+    for check in checks.into_iter().flatten() {
+      match check {
+        Some(true) => {}
+        Some(false) => return Some(false),
+        None => contains = None,
+      }
+    }
+    contains
+  }
+}
+
+impl std::fmt::Display for GameVersions {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match (
+      display_bound(&self.min, self.min_build),
+      display_bound(&self.max, self.max_build),
+    ) {
+      (Some(min), Some(max)) => write!(f, "{} ~ {}", min, max),
+      (Some(min), None) => write!(f, ">= {}", min),
+      (None, Some(max)) => write!(f, "<= {}", max),
+      (None, None) => write!(f, "*"),
+    }
+  }
+}
+
+/// A bound written as [`GameVersion`] is, the build in brackets.
+fn display_bound(client: &Option<String>, build: Option<u64>) -> Option<String> {
+  match (client, build) {
+    (Some(client), Some(build)) => Some(format!("{}（{}）", client, build)),
+    (Some(client), None) => Some(client.to_owned()),
+    (None, Some(build)) => Some(build.to_string()),
+    (None, None) => None,
+  }
+}
+
+/// Compares the client version to a bound, looking only at as many of its
+/// components as the bound has.
+fn compare(version: &GameVersion, bound: &str) -> Option<Ordering> {
+  let bound = parse_version(bound)?;
+  let client = parse_version(version.client.as_deref()?)?;
+  let client = &client[..bound.len().min(client.len())];
+  Some(client.cmp(&bound[..]))
+}
+
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+  version
+    .trim()
+    .split('.')
+    .map(|x| x.parse::<u64>().ok())
+    .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(#[from] std::io::Error),
+  #[error("serde_json: {0}")]
+  SerdeJson(#[from] serde_json::Error),
+  #[error(
+    "Record was written by a newer seamonkey (schema version {0}, supported up to {SCHEMA_VERSION}), please upgrade"
+  )]
+  UnsupportedSchemaVersion(u64),
+  #[error("Record is not a JSON object")]
+  NotAnObject,
+}
+
+/// Whether `path`, relative to `res_mods`, belongs to seamonkey itself rather than to a mod.
+pub fn is_record_file(path: &Path) -> bool {
+  path.components().next().is_some_and(|component| {
+    component
+      .as_os_str()
+      .to_string_lossy()
+      .starts_with(".seamonkey")
+  })
+}
+
+const RECORD_FILE: &str = ".seamonkey";
+const RECORD_TEMP_FILE: &str = ".seamonkey.tmp";
+const RECORD_BACKUP_FILE: &str = ".seamonkey.bak";
+
+async fn read_record_file(record_file: &Path) -> Result<Record, Error> {
+  let file = fs::OpenOptions::new()
+    .read(true)
+    .open(record_file)
+    .await
+    .map_err(Error::Io)?;
+  let mut reader = BufReader::new(file);
+  let mut buf = Vec::new();
+  reader.read_to_end(&mut buf).await.map_err(Error::Io)?;
+  let mut record = serde_json::from_slice(&buf).map_err(Error::SerdeJson)?;
+  let mut disowned = Vec::new();
+  migrate(&mut record, &mut disowned)?;
+  let mut record = serde_json::from_value::<Record>(record).map_err(Error::SerdeJson)?;
+  record.disowned = disowned;
+  Ok(record)
+}
+
+fn migrate(record: &mut serde_json::Value, disowned: &mut Vec<PathBuf>) -> Result<(), Error> {
+  if !record.is_object() {
+    return Err(Error::NotAnObject);
+  }
+  // Records written before versioning have no `schema_version` at all
+  let schema_version = record
+    .get("schema_version")
+    .and_then(serde_json::Value::as_u64)
+    .unwrap_or(0);
+  if schema_version > SCHEMA_VERSION {
+    return Err(Error::UnsupportedSchemaVersion(schema_version));
+  }
+  for (from, migration) in MIGRATIONS.iter().enumerate().skip(schema_version as usize) {
+    debug!("migrate record: v{} -> v{}", from, from + 1);
+    migration(record, disowned);
+    record["schema_version"] = (from as u64 + 1).into();
+  }
+  Ok(())
+}
+
+fn migrate_v0_to_v1(record: &mut serde_json::Value, _: &mut Vec<PathBuf>) {
+  if let Some(installed) = record
+    .get_mut("installed")
+    .and_then(serde_json::Value::as_object_mut)
+  {
+    for record_item in installed.values_mut() {
+      if let Some(record_item) = record_item.as_object_mut() {
+        record_item
+          .entry("file_hashes")
+          .or_insert_with(|| serde_json::json!({}));
+      }
+    }
+  }
+}
+
+/// Records before v2 listed the extracted `seamonkey.toml` among the files of
+/// the mod which shipped it. It is dropped from them, and from disk when the
+/// record is next written.
+fn migrate_v1_to_v2(record: &mut serde_json::Value, disowned: &mut Vec<PathBuf>) {
+  if let Some(installed) = record
+    .get_mut("installed")
+    .and_then(serde_json::Value::as_object_mut)
+  {
+    for record_item in installed.values_mut() {
+      let Some(record_item) = record_item.as_object_mut() else {
+        continue;
+      };
+      if let Some(files) = record_item
+        .get_mut("files")
+        .and_then(serde_json::Value::as_array_mut)
+      {
+        let len = files.len();
+        files.retain(|file| file.as_str() != Some(METADATA_FILE));
+        if files.len() != len && !disowned.iter().any(|x| x == Path::new(METADATA_FILE)) {
+          disowned.push(PathBuf::from(METADATA_FILE));
+        }
+      }
+      if let Some(file_hashes) = record_item
+        .get_mut("file_hashes")
+        .and_then(serde_json::Value::as_object_mut)
+      {
+        file_hashes.remove(METADATA_FILE);
+      }
+    }
+  }
+}
+
+/// Reads the record of `res_mods_dir`, restoring it from `.seamonkey.bak`
+/// when it is corrupted and telling the handler so.
+///
+/// An old record is only migrated in memory, nothing but the restore touches
+/// the disk.
+pub async fn read_record(res_mods_dir: &Path, handler: &dyn Handler) -> Result<Record, Error> {
+  if !fs::try_exists(res_mods_dir.join(RECORD_FILE))
+    .await
+    .map_err(Error::Io)?
+  {
+    return Ok(Record::default());
+  }
+  match read_record_file(&res_mods_dir.join(RECORD_FILE)).await {
+    Err(err @ (Error::SerdeJson(_) | Error::NotAnObject)) => {
+      let backup_file = res_mods_dir.join(RECORD_BACKUP_FILE);
+      if !fs::try_exists(&backup_file).await.map_err(Error::Io)? {
+        return Err(err);
+      }
+      match read_record_file(&backup_file).await {
+        Ok(record) => {
+          warn!("record corrupted ({}), restoring from backup", err);
+          handler.message("安装记录已损坏，已从备份恢复");
+          // The backup is put back as it is, a pending migration is left to
+          // the next write.
+          fs::copy(&backup_file, res_mods_dir.join(RECORD_FILE))
+            .await
+            .map_err(Error::Io)?;
+          Ok(record)
+        }
+        Err(_) => Err(err),
+      }
+    }
+    res => res,
+  }
+}
+
+/// Writes the record to a temporary file and renames it over `.seamonkey`,
+/// so that an interrupted write never leaves a truncated record behind.
+///
+/// The previous record is kept as `.seamonkey.bak` as long as it can still be parsed.
+/// Files disowned by migrating the record are removed once it is written.
+pub async fn write_record(res_mods_dir: &Path, record: &Record) -> Result<(), Error> {
+  let record_file = res_mods_dir.join(RECORD_FILE);
+  let temp_file = res_mods_dir.join(RECORD_TEMP_FILE);
+
+  {
+    let file = fs::OpenOptions::new()
+      .create(true)
+      .truncate(true)
+      .write(true)
+      .open(&temp_file)
+      .await
+      .map_err(Error::Io)?;
+    let mut writer = BufWriter::new(file);
+    writer
+      .write_all(&serde_json::to_vec(&record).map_err(Error::SerdeJson)?)
+      .await
+      .map_err(Error::Io)?;
+    writer.flush().await.map_err(Error::Io)?;
+    writer.into_inner().sync_all().await.map_err(Error::Io)?;
+  }
+
+  if fs::try_exists(&record_file).await.map_err(Error::Io)?
+    && read_record_file(&record_file).await.is_ok()
+  {
+    fs::copy(&record_file, res_mods_dir.join(RECORD_BACKUP_FILE))
+      .await
+      .map_err(Error::Io)?;
+  }
+
+  fs::rename(&temp_file, &record_file)
+    .await
+    .map_err(Error::Io)?;
+
+  #[cfg(unix)]
+  fs::File::open(res_mods_dir)
+    .await
+    .map_err(Error::Io)?
+    .sync_all()
+    .await
+    .map_err(Error::Io)?;
+
+  for file in record.disowned.iter() {
+    if record
+      .installed
+      .values()
+      .any(|record_item| record_item.files.contains(file))
+    {
+      continue;
+    }
+    debug!("remove file disowned by migration: {:?}", file);
+    match fs::remove_file(res_mods_dir.join(file)).await {
+      Ok(()) => {}
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+      Err(err) => warn!("failed to remove {:?}: {}", file, err),
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn dependency(version: &str) -> Dependency {
+    Dependency {
+      id: "a".to_owned(),
+      version: Some(version.to_owned()),
+      update: None,
+    }
+  }
+
+  #[test]
+  fn caret() {
+    let zero = dependency("^0.3");
+    assert!(zero.matches("0.3"));
+    assert!(zero.matches("0.3.9"));
+    assert!(!zero.matches("0.4"));
+    assert!(!zero.matches("0.2.9"));
+
+    let bare = dependency("1.2");
+    assert!(bare.matches("1.9"));
+    assert!(!bare.matches("2.0"));
+  }
+
+  #[test]
+  fn tilde() {
+    let dependency = dependency("~1.2");
+    assert!(dependency.matches("1.2"));
+    assert!(dependency.matches("1.2.7"));
+    assert!(!dependency.matches("1.3"));
+    assert!(!dependency.matches("1.1"));
+  }
+
+  #[test]
+  fn range() {
+    let dependency = dependency(">=1, <2");
+    assert!(dependency.matches("1"));
+    assert!(dependency.matches("1.99"));
+    assert!(!dependency.matches("2"));
+    assert!(!dependency.matches("0.9"));
+  }
+
+  #[test]
+  fn pre_release() {
+    assert!(dependency("=1.2").matches("1.2.0-beta"));
+    assert!(dependency("^1.2").matches("v1.2.0-beta"));
+    assert!(!dependency(">1.2").matches("1.2.0-beta"));
+  }
+
+  #[test]
+  fn wildcard() {
+    assert!(dependency("*").matches("0.0.1"));
+    assert!(dependency("1.*").matches("1.5"));
+    assert!(!dependency("1.*").matches("2.0"));
+    assert!(dependency("1.2.*").matches("1.2.3"));
+    assert!(!dependency("1.2.*").matches("1.3"));
+  }
+
+  fn game_version(build: u64, client: Option<&str>) -> GameVersion {
+    GameVersion {
+      build,
+      client: client.map(str::to_owned),
+    }
+  }
+
+  #[test]
+  fn game_versions_client() {
+    let game_versions = GameVersions {
+      min: Some("24.11".to_owned()),
+      max: Some("25".to_owned()),
+      min_build: None,
+      max_build: None,
+    };
+    assert_eq!(
+      game_versions.contains(&game_version(8753654, Some("25.4.0.8753654"))),
+      Some(true)
+    );
+    assert_eq!(
+      game_versions.contains(&game_version(8753654, Some("24.10.1.8753654"))),
+      Some(false)
+    );
+    assert_eq!(
+      game_versions.contains(&game_version(8753654, Some("26.1.0.8753654"))),
+      Some(false)
+    );
+    assert_eq!(game_versions.contains(&game_version(8753654, None)), None);
+  }
+
+  #[test]
+  fn game_versions_build() {
+    let game_versions = GameVersions {
+      min: Some("25".to_owned()),
+      max: None,
+      min_build: Some(8753654),
+      max_build: None,
+    };
+    assert_eq!(
+      game_versions.contains(&game_version(8753654, Some("25.4.0.8753654"))),
+      Some(true)
+    );
+    // The build is too old whatever the unknown client version is.
+    assert_eq!(
+      game_versions.contains(&game_version(8700000, None)),
+      Some(false)
+    );
+    assert_eq!(game_versions.contains(&game_version(8800000, None)), None);
+  }
+
+  #[test]
+  fn any_version() {
+    let dependency = Dependency {
+      id: "a".to_owned(),
+      version: None,
+      update: None,
+    };
+    assert!(dependency.matches("anything"));
+  }
+}
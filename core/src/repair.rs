@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+
+use async_zip::error::ZipError;
+use log::debug;
+use tokio::{fs, io::BufReader};
+use url::Url;
+
+use crate::{
+  cache::Cache,
+  event::{Event, Handler},
+  extract, install, record,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("Record: {0}")]
+  Record(record::Error),
+  #[error("Zip: {0}")]
+  Zip(ZipError),
+  #[error("Install: {0}")]
+  Install(install::Error),
+  #[error("Url parse: {0}")]
+  UrlParse(url::ParseError),
+  #[error("Unknown URL scheme")]
+  UnknownUrlScheme(String),
+  #[error("Mod not found: {0}")]
+  ModNotFound(String),
+  #[error("Archive not found: {0}")]
+  ArchiveNotFound(String),
+  #[error("Archive changed: expected {0}, got {1}")]
+  ArchiveChanged(String, String),
+  #[error("Extract: {0}")]
+  Extract(extract::Error),
+}
+
+pub async fn repair(
+  res_mods_dir: &Path,
+  item: &str,
+  cache: &Cache,
+  dry_run: bool,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  debug!("repair: {}", item);
+  let mut record = record::read_record(res_mods_dir, handler)
+    .await
+    .map_err(Error::Record)?;
+
+  let (install_id, mut record_item) = record
+    .find(item)
+    .map(|(install_id, record_item)| (install_id.to_owned(), record_item.to_owned()))
+    .ok_or_else(|| Error::ModNotFound(item.to_owned()))?;
+
+  let archive = fetch_archive(&record_item.from, &record_item.sha256, cache, handler).await?;
+  let sha256 = sha256::try_async_digest(&archive)
+    .await
+    .map_err(Error::Io)?;
+  if sha256 != record_item.sha256 {
+    return Err(Error::ArchiveChanged(record_item.sha256, sha256));
+  }
+
+  let mut zip = async_zip::tokio::read::seek::ZipFileReader::with_tokio(BufReader::new(
+    fs::File::open(&archive).await.map_err(Error::Io)?,
+  ))
+  .await
+  .map_err(Error::Zip)?;
+  extract::check_entries(zip.file().entries()).map_err(Error::Extract)?;
+
+  let mut repaired = 0;
+  for (index, entry) in zip.file().entries().to_vec().iter().enumerate() {
+    if entry.dir().map_err(Error::Zip)? {
+      continue;
+    }
+    let file_path = extract::entry_path(entry.filename().as_str().map_err(Error::Zip)?)
+      .map_err(Error::Extract)?;
+    if !record_item.files.contains(&file_path) {
+      continue;
+    }
+
+    let buf = extract::read_to_end(
+      zip.reader_without_entry(index).await.map_err(Error::Zip)?,
+      entry,
+    )
+    .await
+    .map_err(Error::Extract)?;
+    let expected = record::FileHash {
+      sha256: sha256::digest(&buf),
+      size: buf.len() as u64,
+    };
+
+    extract::ensure_within(res_mods_dir, &file_path)
+      .await
+      .map_err(Error::Extract)?;
+    let target_path = res_mods_dir.join(&file_path);
+    let intact = fs::try_exists(&target_path).await.map_err(Error::Io)?
+      && record::FileHash::of(&target_path)
+        .await
+        .map_err(Error::Io)?
+        == expected;
+    if !intact && dry_run {
+      handler.message(&format!("[演练] 将修复：{}", file_path.to_string_lossy()));
+      repaired += 1;
+    } else if !intact {
+      if let Some(parent) = target_path.parent() {
+        fs::create_dir_all(parent).await.map_err(Error::Io)?;
+      }
+      fs::write(&target_path, &buf).await.map_err(Error::Io)?;
+      handler.message(&format!("已修复：{}", file_path.to_string_lossy()));
+      handler.event(&Event::FileRepaired { path: &file_path });
+      repaired += 1;
+    }
+    record_item.file_hashes.insert(file_path, expected);
+  }
+
+  if dry_run {
+    return Ok(());
+  }
+
+  record.installed.insert(install_id, record_item);
+  record::write_record(res_mods_dir, &record)
+    .await
+    .map_err(Error::Record)?;
+
+  if repaired == 0 {
+    handler.message("所有文件完好，无需修复");
+  }
+
+  Ok(())
+}
+
+async fn fetch_archive(
+  from: &str,
+  expected_sha256: &str,
+  cache: &Cache,
+  handler: &dyn Handler,
+) -> Result<PathBuf, Error> {
+  let url = Url::parse(from).map_err(Error::UrlParse)?;
+  match url.scheme() {
+    "file" => {
+      let path = url
+        .to_file_path()
+        .map_err(|_| Error::ArchiveNotFound(from.to_owned()))?;
+      if fs::try_exists(&path).await.map_err(Error::Io)? {
+        Ok(path)
+      } else {
+        Err(Error::ArchiveNotFound(from.to_owned()))
+      }
+    }
+    "http" | "https" => install::download(
+      &url,
+      cache,
+      Some(expected_sha256),
+      &mut reqwest::Client::new(),
+      handler,
+    )
+    .await
+    .map_err(Error::Install),
+    scheme => Err(Error::UnknownUrlScheme(scheme.to_owned())),
+  }
+}
@@ -0,0 +1,127 @@
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+};
+
+use log::debug;
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::dirs;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("TOML: {0}")]
+  DeToml(toml::de::Error),
+  #[error("Invalid public key of publisher {0}: {1}")]
+  InvalidPublicKey(String, minisign_verify::Error),
+  #[error("Invalid signature: {0}")]
+  InvalidSignature(minisign_verify::Error),
+  #[error("Signature does not match the archive, signed by {0}")]
+  BadSignature(String),
+  #[error("Signed by an untrusted key")]
+  UntrustedSignature,
+}
+
+/// A publisher whose archives are trusted when signed by its key.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Publisher {
+  /// A minisign public key, as the base64 line of a `.pub` file.
+  pub public_key: String,
+  /// The `Metadata.authors` this key is allowed to sign for, the name of
+  /// the publisher when left empty.
+  #[serde(default)]
+  pub authors: Vec<String>,
+}
+
+/// Trusted publisher keys, read from a TOML file such as:
+///
+/// ```toml
+/// [publishers.localized_korabli]
+/// public_key = "RWQ..."
+/// authors = ["北斗余晖"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Keyring {
+  #[serde(default)]
+  pub publishers: BTreeMap<String, Publisher>,
+}
+
+impl Keyring {
+  pub fn default_path() -> PathBuf {
+    dirs::config_dir().join("keyring.toml")
+  }
+
+  /// Reads the keyring, which is empty when the file does not exist.
+  pub async fn load(path: &Path) -> Result<Self, Error> {
+    match fs::read_to_string(path).await {
+      Ok(content) => {
+        let mut keyring = toml::from_str::<Keyring>(&content).map_err(Error::DeToml)?;
+        for (name, publisher) in keyring.publishers.iter_mut() {
+          PublicKey::from_base64(publisher.public_key.trim())
+            .map_err(|err| Error::InvalidPublicKey(name.to_owned(), err))?;
+          if publisher.authors.is_empty() {
+            publisher.authors.push(name.to_owned());
+          }
+        }
+        Ok(keyring)
+      }
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(err) => Err(Error::Io(err)),
+    }
+  }
+
+  /// Checks a detached minisign `signature` of `archive` and returns the name
+  /// of the trusted publisher who made it.
+  pub async fn verify(&self, archive: &Path, signature: &str) -> Result<&str, Error> {
+    let signature = Signature::decode(signature).map_err(Error::InvalidSignature)?;
+    for (name, publisher) in self.publishers.iter() {
+      let public_key = PublicKey::from_base64(publisher.public_key.trim())
+        .map_err(|err| Error::InvalidPublicKey(name.to_owned(), err))?;
+      let mut verifier = match public_key.verify_stream(&signature) {
+        Ok(verifier) => verifier,
+        Err(minisign_verify::Error::UnexpectedKeyId) => continue,
+        Err(err) => return Err(Error::InvalidSignature(err)),
+      };
+
+      let mut file = fs::File::open(archive).await.map_err(Error::Io)?;
+      let mut buf = vec![0; 64 * 1024];
+      loop {
+        let len = file.read(&mut buf).await.map_err(Error::Io)?;
+        if len == 0 {
+          break;
+        }
+        verifier.update(&buf[..len]);
+      }
+      return match verifier.finalize() {
+        Ok(()) => {
+          debug!("{:?} signed by {}", archive, name);
+          Ok(name)
+        }
+        Err(_) => Err(Error::BadSignature(name.to_owned())),
+      };
+    }
+    Err(Error::UntrustedSignature)
+  }
+
+  pub fn publisher(&self, name: &str) -> Option<&Publisher> {
+    self.publishers.get(name)
+  }
+
+  /// The publishers allowed to sign for any of `authors`.
+  pub fn publishers_of<'a>(&'a self, authors: &'a [String]) -> impl Iterator<Item = &'a str> {
+    self
+      .publishers
+      .iter()
+      .filter(|(_, publisher)| {
+        publisher
+          .authors
+          .iter()
+          .any(|author| authors.contains(author))
+      })
+      .map(|(name, _)| name.as_str())
+  }
+}
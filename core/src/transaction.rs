@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use tokio::fs;
+use uuid::Uuid;
+
+#[derive(Debug)]
+enum Operation {
+  CreateDir(PathBuf),
+  CreateFile(PathBuf),
+  RemoveFile(PathBuf, PathBuf),
+}
+
+/// Journals every change made under `res_mods` so that it can be undone.
+///
+/// Removed files are moved into a hidden directory inside `res_mods` instead of being
+/// deleted, which keeps the move a cheap rename on the same file system.
+#[derive(Debug)]
+pub struct Transaction {
+  res_mods_dir: PathBuf,
+  backup_dir: PathBuf,
+  journal: Vec<Operation>,
+}
+
+impl Transaction {
+  pub async fn begin(res_mods_dir: &Path) -> Result<Self, std::io::Error> {
+    let backup_dir = res_mods_dir.join(format!(".seamonkey-txn-{}", Uuid::new_v4()));
+    fs::create_dir_all(&backup_dir).await?;
+    Ok(Self {
+      res_mods_dir: res_mods_dir.to_owned(),
+      backup_dir,
+      journal: Vec::new(),
+    })
+  }
+
+  pub async fn create_dir_all(&mut self, relative_path: &Path) -> Result<(), std::io::Error> {
+    let mut to_create = Vec::new();
+    let mut current = Some(relative_path);
+    while let Some(path) = current.filter(|path| !path.as_os_str().is_empty()) {
+      if fs::try_exists(self.res_mods_dir.join(path)).await? {
+        break;
+      }
+      to_create.push(path.to_owned());
+      current = path.parent();
+    }
+    for path in to_create.into_iter().rev() {
+      fs::create_dir(self.res_mods_dir.join(&path)).await?;
+      self.journal.push(Operation::CreateDir(path));
+    }
+    Ok(())
+  }
+
+  /// Creates a new file, failing if one already exists at `relative_path`.
+  pub async fn create_file(&mut self, relative_path: &Path) -> Result<fs::File, std::io::Error> {
+    if let Some(parent) = relative_path.parent() {
+      self.create_dir_all(parent).await?;
+    }
+    let file = fs::OpenOptions::new()
+      .create_new(true)
+      .write(true)
+      .open(self.res_mods_dir.join(relative_path))
+      .await?;
+    self
+      .journal
+      .push(Operation::CreateFile(relative_path.to_owned()));
+    Ok(file)
+  }
+
+  /// Moves a file out of the way, doing nothing if it does not exist.
+  pub async fn remove_file(&mut self, relative_path: &Path) -> Result<(), std::io::Error> {
+    let target_path = self.res_mods_dir.join(relative_path);
+    if !fs::try_exists(&target_path).await? {
+      return Ok(());
+    }
+    let backup_path = self.backup_dir.join(format!("{}", self.journal.len()));
+    fs::rename(&target_path, &backup_path).await?;
+    self
+      .journal
+      .push(Operation::RemoveFile(relative_path.to_owned(), backup_path));
+    Ok(())
+  }
+
+  pub async fn commit(self) {
+    debug!("commit transaction: {:?}", self.journal);
+    if let Err(err) = fs::remove_dir_all(&self.backup_dir).await {
+      warn!("failed to clean up {:?}: {}", self.backup_dir, err);
+    }
+  }
+
+  /// Undoes every journaled operation in reverse order, carrying on past failures.
+  pub async fn rollback(mut self) -> Result<(), std::io::Error> {
+    debug!("rollback transaction: {:?}", self.journal);
+    let mut result = Ok(());
+    while let Some(operation) = self.journal.pop() {
+      let res = match &operation {
+        Operation::CreateDir(path) => fs::remove_dir(self.res_mods_dir.join(path)).await,
+        Operation::CreateFile(path) => fs::remove_file(self.res_mods_dir.join(path)).await,
+        Operation::RemoveFile(path, backup_path) => {
+          fs::rename(backup_path, self.res_mods_dir.join(path)).await
+        }
+      };
+      if let Err(err) = res {
+        warn!("failed to undo {:?}: {}", operation, err);
+        if result.is_ok() {
+          result = Err(err);
+        }
+      }
+    }
+    if result.is_ok() {
+      result = fs::remove_dir_all(&self.backup_dir).await;
+    }
+    result
+  }
+}
@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use log::debug;
+
+use crate::{
+  event::{Event, Handler},
+  record,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("Record: {0}")]
+  Record(record::Error),
+  #[error("Mod not found: {0}")]
+  ModNotFound(String),
+  #[error("{0} is required by {1:?}")]
+  RequiredBy(String, Vec<String>),
+}
+
+/// Uninstalls mods, refusing to remove one that another installed mod
+/// depends on, or removing those dependents too when `cascade` is set.
+pub async fn uninstall(
+  res_mods_dir: &Path,
+  items: Vec<String>,
+  cascade: bool,
+  dry_run: bool,
+  handler: &dyn Handler,
+) -> Result<(), Error> {
+  debug!("uninstall: {:?}", items);
+  let mut record = record::read_record(res_mods_dir, handler)
+    .await
+    .map_err(Error::Record)?;
+
+  let found = items
+    .iter()
+    .map(|item| {
+      record
+        .find(item)
+        .map(|(install_id, record)| (install_id.to_owned(), record.to_owned()))
+        .ok_or(Error::ModNotFound(item.to_owned()))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  let found = with_dependents(&record, found, cascade, handler)?;
+
+  let mut files_to_uninstall = Vec::new();
+  let mut uninstalled = Vec::new();
+  for (install_id, record_item) in found {
+    if record.installed.remove(&install_id).is_some() {
+      if dry_run {
+        match &record_item.metadata {
+          Some(metadata) => {
+            handler.message(&format!("[演练] 将卸载{}({})", metadata.name, metadata.id))
+          }
+          None => handler.message(&format!("[演练] 将卸载{}", install_id)),
+        }
+      }
+      files_to_uninstall.extend(record_item.files);
+      uninstalled.push((install_id, record_item.metadata.map(|x| x.id)));
+    }
+  }
+
+  if dry_run {
+    for file in files_to_uninstall {
+      handler.message(&format!("[演练] 将删除：{}", file.to_string_lossy()));
+    }
+    return Ok(());
+  }
+
+  record::write_record(res_mods_dir, &record)
+    .await
+    .map_err(Error::Record)?;
+
+  for file in files_to_uninstall {
+    tokio::fs::remove_file(res_mods_dir.join(file))
+      .await
+      .map_err(Error::Io)?;
+  }
+
+  for (install_id, id) in uninstalled.iter() {
+    handler.event(&Event::ModUninstalled {
+      install_id,
+      id: id.as_deref(),
+    });
+  }
+
+  Ok(())
+}
+
+/// Adds the installed mods which depend on the mods in `found`, over and over
+/// until nothing is added, or fails on the first such mod unless `cascade`.
+fn with_dependents(
+  record: &record::Record,
+  mut found: Vec<(String, record::RecordItem)>,
+  cascade: bool,
+  handler: &dyn Handler,
+) -> Result<Vec<(String, record::RecordItem)>, Error> {
+  loop {
+    let kept = record
+      .installed
+      .iter()
+      .filter(|(install_id, _)| !found.iter().any(|(x, _)| x == *install_id))
+      .filter_map(|(_, record_item)| record_item.metadata.as_ref())
+      .collect::<Vec<_>>();
+    // A capability stays available while a kept mod still provides it.
+    let removed_ids = found
+      .iter()
+      .filter_map(|(_, record_item)| record_item.metadata.as_ref())
+      .flat_map(|metadata| {
+        std::iter::once(metadata.id.as_str()).chain(
+          metadata.provides.iter().map(String::as_str).filter(|x| {
+            !kept
+              .iter()
+              .any(|metadata_| metadata_.provides.iter().any(|y| y == x))
+          }),
+        )
+      })
+      .collect::<Vec<_>>();
+    let mut dependents = record
+      .installed
+      .iter()
+      .filter(|(install_id, _)| !found.iter().any(|(x, _)| x == *install_id))
+      .filter_map(|(install_id, record_item)| {
+        let metadata = record_item.metadata.as_ref()?;
+        let required = metadata
+          .depends
+          .iter()
+          .find(|dependency| removed_ids.contains(&dependency.id.as_str()))?;
+        Some((install_id, record_item, metadata, required.id.to_owned()))
+      })
+      .collect::<Vec<_>>();
+    if dependents.is_empty() {
+      return Ok(found);
+    }
+    dependents.sort_by(|a, b| a.0.cmp(b.0));
+
+    if !cascade {
+      let (_, _, _, required) = &dependents[0];
+      return Err(Error::RequiredBy(
+        required.to_owned(),
+        dependents
+          .iter()
+          .filter(|(_, _, _, x)| x == required)
+          .map(|(_, _, metadata, _)| metadata.id.to_owned())
+          .collect(),
+      ));
+    }
+    for (install_id, record_item, metadata, required) in dependents {
+      handler.message(&format!("{}依赖于{}，将一并卸载", metadata.id, required));
+      found.push((install_id.to_owned(), record_item.to_owned()));
+    }
+  }
+}
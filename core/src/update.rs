@@ -1,8 +1,6 @@
 use std::path::Path;
 
-use temp_dir::TempDir;
-
-use crate::{install, record};
+use crate::{cache::Cache, event::Handler, install, record, signature::Keyring};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -14,19 +12,21 @@ pub enum Error {
 
 pub async fn update(
   res_mods_dir: &Path,
-  temp_dir: &TempDir,
-  yes_for_all: bool,
+  cache: &Cache,
+  keyring: &Keyring,
+  flags: install::Flags,
+  handler: &dyn Handler,
 ) -> Result<(), Error> {
-  let record = record::read_record(res_mods_dir)
+  let record = record::read_record(res_mods_dir, handler)
     .await
     .map_err(Error::Record)?;
   let update_items = record
     .installed
-    .iter()
-    .filter_map(|(_, x)| x.metadata.to_owned())
+    .values()
+    .filter_map(|x| x.metadata.to_owned())
     .map(|x| x.update)
     .collect::<Vec<_>>();
-  install::install(res_mods_dir, update_items, temp_dir, yes_for_all)
+  install::install(res_mods_dir, update_items, cache, keyring, flags, handler)
     .await
     .map_err(Error::Install)?;
   Ok(())
@@ -0,0 +1,129 @@
+use std::{
+  collections::HashSet,
+  path::{Path, PathBuf},
+};
+
+use log::debug;
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{event::Handler, record};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO: {0}")]
+  Io(std::io::Error),
+  #[error("Record: {0}")]
+  Record(record::Error),
+  #[error("Mod not found: {0}")]
+  ModNotFound(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+  Missing,
+  Modified,
+  Unowned,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+  pub kind: ProblemKind,
+  pub path: PathBuf,
+  pub install_id: Option<String>,
+}
+
+/// Checks the files of the given mods (or of every mod, when `items` is empty)
+/// against the hashes in the record.
+///
+/// When every mod is checked, files under `res_mods` which are not owned by
+/// any mod are reported as well.
+pub async fn check(
+  res_mods_dir: &Path,
+  items: &[String],
+  handler: &dyn Handler,
+) -> Result<Vec<Problem>, Error> {
+  let record = record::read_record(res_mods_dir, handler)
+    .await
+    .map_err(Error::Record)?;
+
+  let to_check = if items.is_empty() {
+    record.installed.iter().collect::<Vec<_>>()
+  } else {
+    items
+      .iter()
+      .map(|item| record.find(item).ok_or(Error::ModNotFound(item.to_owned())))
+      .collect::<Result<Vec<_>, _>>()?
+  };
+
+  let mut problems = Vec::new();
+  for (install_id, record_item) in to_check {
+    for file in record_item.files.iter() {
+      let target_path = res_mods_dir.join(file);
+      let kind = if !fs::try_exists(&target_path).await.map_err(Error::Io)? {
+        Some(ProblemKind::Missing)
+      } else if let Some(expected) = record_item.file_hashes.get(file) {
+        let size = fs::metadata(&target_path).await.map_err(Error::Io)?.len();
+        if size != expected.size
+          || record::FileHash::of(&target_path)
+            .await
+            .map_err(Error::Io)?
+            != *expected
+        {
+          Some(ProblemKind::Modified)
+        } else {
+          None
+        }
+      } else {
+        debug!("no hash recorded for {:?}, skipped", file);
+        None
+      };
+      if let Some(kind) = kind {
+        problems.push(Problem {
+          kind,
+          path: file.to_owned(),
+          install_id: Some(install_id.to_owned()),
+        });
+      }
+    }
+  }
+
+  if items.is_empty() {
+    let owned = record
+      .installed
+      .values()
+      .flat_map(|record_item| record_item.files.iter())
+      .collect::<HashSet<_>>();
+    for file in walk_files(res_mods_dir).await.map_err(Error::Io)? {
+      if !owned.contains(&file) && !record::is_record_file(&file) {
+        problems.push(Problem {
+          kind: ProblemKind::Unowned,
+          path: file,
+          install_id: None,
+        });
+      }
+    }
+  }
+
+  Ok(problems)
+}
+
+/// Lists every regular file under `dir`, relative to `dir`.
+async fn walk_files(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+  let mut files = Vec::new();
+  let mut dirs = vec![PathBuf::new()];
+  while let Some(relative_dir) = dirs.pop() {
+    let mut read_dir = fs::read_dir(dir.join(&relative_dir)).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+      let relative_path = relative_dir.join(entry.file_name());
+      if entry.file_type().await?.is_dir() {
+        dirs.push(relative_path);
+      } else {
+        files.push(relative_path);
+      }
+    }
+  }
+  files.sort();
+  Ok(files)
+}
@@ -4,23 +4,130 @@
 )]
 
 use std::cell::RefCell;
-use std::collections::VecDeque;
 use std::env::current_dir;
-use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::mpsc::{self, TryRecvError};
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::sync::Mutex;
 use std::time::Duration;
 
 use gtk::prelude::*;
 use gtk::{glib, Application, ApplicationWindow};
 use gtk4 as gtk;
+use seamonkey_core::{
+  cache::{self, Cache},
+  config::Config,
+  detect, event, game, install,
+  signature::Keyring,
+  ModManager,
+};
 
 #[derive(Debug, Clone)]
 enum Event {
   LogUpdate(String),
 }
 
+/// Collects the messages of the core into the log shown in the window.
+struct LogHandler {
+  log: Mutex<String>,
+  ev_tx: Sender<Event>,
+}
+
+impl LogHandler {
+  fn append(&self, line: &str) {
+    let mut log = self.log.lock().expect("wtf log lock");
+    log.push_str(line);
+    log.push('\n');
+    let _ = self.ev_tx.send(Event::LogUpdate(log.to_owned()));
+  }
+}
+
+impl event::Handler for LogHandler {
+  fn event(&self, event: &event::Event) {
+    if let event::Event::Message { text } = event {
+      self.append(text);
+    }
+  }
+
+  fn confirm(&self, _kind: event::Prompt, text: &str, default: bool) -> bool {
+    self.append(text);
+    default
+  }
+}
+
+fn install(game_dir: PathBuf, items: Vec<String>, ev_tx: Sender<Event>) {
+  let handler = LogHandler {
+    log: Mutex::new(String::new()),
+    ev_tx,
+  };
+  let runtime = match tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+  {
+    Ok(runtime) => runtime,
+    Err(err) => {
+      handler.append(&format!("启动Mod管理器核心失败：{}", err));
+      return;
+    }
+  };
+  runtime.block_on(async move {
+    let res_mods_dir = match game::find_res_mods_dir(&game_dir, None).await {
+      Ok(res_mods_dir) => res_mods_dir,
+      Err(err) => {
+        handler.append(&format!("游戏目录错误：{}", err));
+        return;
+      }
+    };
+    let cache = Cache::new(
+      Cache::default_dir(),
+      cache::DEFAULT_MAX_SIZE,
+      cache::DEFAULT_MAX_AGE,
+    );
+    let keyring = match Keyring::load(&Keyring::default_path()).await {
+      Ok(keyring) => keyring,
+      Err(err) => {
+        handler.append(&format!("读取受信任的发布者密钥出错：{}", err));
+        return;
+      }
+    };
+    let manager = ModManager::new(res_mods_dir, cache, keyring, Box::new(handler));
+    let flags = install::Flags {
+      yes_for_all: true,
+      ..Default::default()
+    };
+    match manager.install(items, flags).await {
+      Ok(()) => manager.handler().message("Mod更新已完成"),
+      Err(err) => manager.handler().message(&format!("Mod更新失败：{}", err)),
+    }
+  });
+}
+
+/// The default game of the config, else the first game install found, else
+/// the current dir.
+fn default_game_dir() -> PathBuf {
+  tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .ok()
+    .and_then(|runtime| {
+      runtime.block_on(async {
+        let from_config = Config::load(&Config::default_path())
+          .await
+          .ok()
+          .and_then(|config| config.game_dir(None).ok().flatten().map(Path::to_path_buf));
+        match from_config {
+          Some(game_dir) => Some(game_dir),
+          None => detect::detect()
+            .await
+            .into_iter()
+            .next()
+            .map(|candidate| candidate.game_dir),
+        }
+      })
+    })
+    .unwrap_or_else(|| current_dir().expect("wtf current_dir"))
+}
+
 fn main() -> glib::ExitCode {
   env_logger::init();
 
@@ -31,21 +138,7 @@ fn main() -> glib::ExitCode {
   application.connect_activate(|app| {
     let (ev_tx, ev_rx) = mpsc::channel::<Event>();
     let mods_to_install = Rc::new(RefCell::new(Vec::<String>::new()));
-    let seamonkey_cli_path = Rc::new(RefCell::new(current_dir().expect("wtf current_dir").join(
-      format!(
-        "seamonkey_cli{}",
-        if cfg!(target_os = "linux") || cfg!(target_os = "macos") {
-          ""
-        } else if cfg!(target_os = "windows") {
-          ".exe"
-        } else {
-          #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "linux")))]
-          compile_error!("This program only supports Windows and Linux!");
-          ""
-        }
-      ),
-    )));
-    let game_dir_path = Rc::new(RefCell::new(current_dir().expect("wtf current_dir")));
+    let game_dir_path = Rc::new(RefCell::new(default_game_dir()));
 
     let window = ApplicationWindow::builder()
       .application(app)
@@ -61,49 +154,6 @@ fn main() -> glib::ExitCode {
     vbox.set_margin_end(10);
     window.set_child(Some(&vbox));
 
-    let seamonkey_cli_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
-    {
-      let seamonkey_cli_entry = gtk::Entry::new();
-      seamonkey_cli_entry.set_placeholder_text(Some("seamonkey_cli位置"));
-      seamonkey_cli_entry.set_text(
-        seamonkey_cli_path
-          .borrow()
-          .to_string_lossy()
-          .to_string()
-          .as_str(),
-      );
-      seamonkey_cli_entry.set_hexpand(true);
-      seamonkey_cli_entry.set_editable(false);
-      seamonkey_cli_box.append(&seamonkey_cli_entry);
-
-      let seamonkey_cli_choose_btn = gtk::Button::with_label("打开");
-      seamonkey_cli_choose_btn.connect_clicked({
-        let window = window.to_owned();
-        let seamonkey_cli_entry = seamonkey_cli_entry.to_owned();
-        let seamonkey_cli_path = seamonkey_cli_path.to_owned();
-        move |_| {
-          let file_dialog = gtk::FileDialog::new();
-
-          file_dialog.open(Some(&window), None::<&gio::Cancellable>, {
-            let seamonkey_cli_entry = seamonkey_cli_entry.to_owned();
-            let seamonkey_cli_path = seamonkey_cli_path.to_owned();
-            move |result| {
-              if let Ok(file) = result {
-                if let Some(file_path) = file.path() {
-                  let file_path_string = file_path.to_string_lossy().to_string();
-                  seamonkey_cli_entry.set_text(&file_path_string);
-                  let mut seamonkey_cli_path = seamonkey_cli_path.borrow_mut();
-                  *seamonkey_cli_path = file_path;
-                }
-              }
-            }
-          });
-        }
-      });
-      seamonkey_cli_box.append(&seamonkey_cli_choose_btn);
-    }
-    vbox.append(&seamonkey_cli_box);
-
     let game_dir_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
     {
       let game_dir_entry = gtk::Entry::new();
@@ -182,65 +232,18 @@ fn main() -> glib::ExitCode {
 
     let button = gtk::Button::with_label("安装/更新");
     button.connect_clicked({
-      let window = window.to_owned();
       let text_view = text_view.to_owned();
       let mods_to_install = mods_to_install.to_owned();
       let game_dir_path = game_dir_path.to_owned();
-      let seamonkey_cli_path = seamonkey_cli_path.to_owned();
 
       move |_| {
         let mods_to_install = mods_to_install.borrow().to_owned();
-        println!("{:?}", seamonkey_cli_path.borrow().as_path());
-        println!("{:?}", game_dir_path.borrow().as_path());
-        match Command::new(seamonkey_cli_path.borrow().as_path())
-          .arg("-yg")
-          .arg(game_dir_path.borrow().as_path())
-          .arg("install")
-          .args(mods_to_install)
-          .stdout(Stdio::piped())
-          .spawn()
-        {
-          Ok(mut child) => {
-            let buffer = text_view.buffer();
-            buffer.set_text("");
-
-            if let Some(stdout) = child.stdout.take() {
-              std::thread::spawn({
-                let ev_tx = ev_tx.to_owned();
-                move || {
-                  let mut reader = BufReader::new(stdout);
-                  let mut all_buf = String::new();
-                  let mut buf = String::new();
-                  while let Ok(x) = reader.read_line(&mut buf) {
-                    if x == 0 {
-                      break;
-                    }
-                    all_buf += &buf;
-                    let _ = ev_tx.send(Event::LogUpdate(all_buf.to_owned()));
-                  }
-                  all_buf += "Mod更新已完成";
-                  let _ = ev_tx.send(Event::LogUpdate(all_buf.to_owned()));
-                }
-              });
-            }
-          }
-          Err(err) => {
-            let dialog = gtk::MessageDialog::builder()
-              .transient_for(&window)
-              .modal(true)
-              .message_type(gtk::MessageType::Warning)
-              .buttons(gtk::ButtonsType::Ok)
-              .title("错误")
-              .text(format!("运行Mod管理器核心失败：{}", err))
-              .build();
-
-            eprintln!("{:?}", err);
-
-            dialog.run_async(|dialog, _| {
-              dialog.close();
-            });
-          }
-        }
+        let game_dir = game_dir_path.borrow().to_owned();
+        text_view.buffer().set_text("");
+        std::thread::spawn({
+          let ev_tx = ev_tx.to_owned();
+          move || install(game_dir, mods_to_install, ev_tx)
+        });
       }
     });
     vbox.append(&button);